@@ -6,7 +6,7 @@ fn main() {
         println!("Running npm run dist for release...");
         // Navigate to the web directory and run npm run dist
         Command::new("npm")
-            .args(&["run", "dist"])
+            .args(["run", "dist"])
             .current_dir("./web")
             .status()
             .unwrap();
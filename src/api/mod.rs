@@ -1,22 +1,27 @@
 use std::convert::Infallible;
 use std::sync::Arc;
 
-use include_dir::{Dir, include_dir};
-use warp::{self, Filter, Reply};
-use warp::http::Method;
+use warp::{self, Filter, Rejection, Reply};
+use warp::http::{Method, StatusCode};
 
-use crate::api::models::{HealthCheckResponse, SearchRequest, SearchResponse, StandardResponse};
-use crate::search::{FileSearchEngine, SearchEngine, SearchOptions};
+use crate::api::models::{CodeIntelRequest, CodeIntelResponse, HealthCheckResponse, IndexStatusResponse, RepoIndexStatus, ReferencesRequest, ReferencesResponse, SearchRequest, SearchResponse, StandardResponse};
+use crate::search::{CodeIntelOptions, FileSearchEngine, ReferenceOptions, ReferenceTarget, SearchEngine, SearchError, SearchOptions};
 
 mod models;
 
 // static WEB_DIR: Dir = include_dir!("$CARGO_MANIFEST_DIR/web/dist");
 
-pub async fn start_api(engine: FileSearchEngine) {
+pub async fn start_api(engine_arc: Arc<FileSearchEngine>) {
     log::info!("Starting API server...");
 
-    let engine_arc = Arc::new(engine);
+    log::info!("API server running on http://127.0.0.1:3030");
+    warp::serve(api_routes(engine_arc))
+        .run(([127, 0, 0, 1], 3030)).await;
+}
 
+/// The routes under `/api`. Requests that match none of them, or whose body can't be read,
+/// get the same error body as failed searches.
+fn api_routes(engine_arc: Arc<FileSearchEngine>) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
     let cors_filter = warp::cors()
         .allow_any_origin()
         .allow_methods(vec![Method::GET, Method::POST, Method::PUT, Method::DELETE])
@@ -59,17 +64,40 @@ pub async fn start_api(engine: FileSearchEngine) {
                     status: "ok".to_string(),
                 }),
                 error: None,
+                error_code: None,
+                error_position: None,
                 time_taken: Some(start_time.elapsed().as_millis() as u64),
             };
             warp::reply::json(&response)
-        })
-        .with(cors_filter.clone());
+        });
 
+    let status_engine_arc = engine_arc.clone();
+    let status_route = warp::path("status")
+        .and(warp::get())
+        .map(move || {
+            log::info!("Received index status request");
+            let start_time = std::time::Instant::now();
+            let repos = status_engine_arc.get_config().repos.iter()
+                .map(|repo| RepoIndexStatus {
+                    name: repo.name.clone(),
+                    skipped_files: status_engine_arc.get_skipped_files(&repo.name),
+                })
+                .collect();
+            let response = StandardResponse {
+                data: Some(IndexStatusResponse { repos }),
+                error: None,
+                error_code: None,
+                error_position: None,
+                time_taken: Some(start_time.elapsed().as_millis() as u64),
+            };
+            warp::reply::json(&response)
+        });
 
+    let search_engine_arc = engine_arc.clone();
     let search_route = warp::path("search")
         .and(warp::post())
         .and(warp::body::json())
-        .and(warp::any().map(move || engine_arc.clone()))
+        .and(warp::any().map(move || search_engine_arc.clone()))
         .and_then(move |request: SearchRequest, engine: Arc<FileSearchEngine>| async move {
             log::info!("Received search request: {:?}", request.query);
             let start_time = std::time::Instant::now();
@@ -78,37 +106,212 @@ pub async fn start_api(engine: FileSearchEngine) {
             let limit = request.limit.unwrap_or(10);
             let results = engine.search(SearchOptions {
                 query,
+                mode: request.mode.unwrap_or_default(),
+                sort_by: request.sort_by.unwrap_or_default(),
                 limit,
+                offset: request.offset.unwrap_or(0),
+                cursor: request.cursor,
+                include_content: request.include_content.unwrap_or(false),
+                context_lines: request.context_lines.unwrap_or(2),
+                explain: request.explain.unwrap_or(false),
             }).await;
             match results {
                 Ok(results) => {
-                    log::info!("Search successful, returning results");
+                    log::info!("Search successful, returning {} of {} results", results.results.len(), results.total);
+                    let time_taken = start_time.elapsed().as_millis() as u64;
                     let response = StandardResponse {
-                        data: Some(results),
+                        data: Some(SearchResponse {
+                            results: results.results,
+                            total: results.total,
+                            offset: results.offset,
+                            next_cursor: results.next_cursor,
+                            facets: results.facets,
+                            time_taken,
+                        }),
                         error: None,
-                        time_taken: Some(start_time.elapsed().as_millis() as u64),
+                        error_code: None,
+                        error_position: None,
+                        time_taken: Some(time_taken),
                     };
-                    Ok::<_, warp::Rejection>(warp::reply::json(&response))
+                    Ok::<_, warp::Rejection>(warp::reply::with_status(warp::reply::json(&response), StatusCode::OK))
                 }
-                Err(err) => {
-                    log::error!("Search failed: {:?}", err);
-                    let response = StandardResponse::<Vec<u8>> { // Assuming no data in case of error
-                        data: None,
-                        error: Some(err.to_string()),
-                        time_taken: Some(start_time.elapsed().as_millis() as u64),
+                Err(err) => Ok::<_, warp::Rejection>(error_reply("Search failed", err, start_time)),
+            }
+        });
+
+    let references_engine_arc = engine_arc.clone();
+    let references_route = warp::path("references")
+        .and(warp::post())
+        .and(warp::body::json())
+        .and(warp::any().map(move || references_engine_arc.clone()))
+        .and_then(move |request: ReferencesRequest, engine: Arc<FileSearchEngine>| async move {
+            log::info!("Received references request: {:?}", request.name);
+            let start_time = std::time::Instant::now();
+
+            let limit = request.limit.unwrap_or(100);
+            let references = match reference_target(request) {
+                Ok(target) => engine.find_references(ReferenceOptions { target, limit }).await,
+                Err(err) => Err(err),
+            };
+            match references {
+                Ok(references) => {
+                    log::info!("Found {} definitions and {} references of {}", references.total_definitions, references.total_references, references.name);
+                    let time_taken = start_time.elapsed().as_millis() as u64;
+                    let response = StandardResponse {
+                        data: Some(ReferencesResponse {
+                            name: references.name,
+                            files: references.files,
+                            total_definitions: references.total_definitions,
+                            total_references: references.total_references,
+                            time_taken,
+                        }),
+                        error: None,
+                        error_code: None,
+                        error_position: None,
+                        time_taken: Some(time_taken),
                     };
-                    Ok::<_, warp::Rejection>(warp::reply::json(&response))
+                    Ok::<_, warp::Rejection>(warp::reply::with_status(warp::reply::json(&response), StatusCode::OK))
                 }
+                Err(err) => Ok::<_, warp::Rejection>(error_reply("Finding references failed", err, start_time)),
             }
-        })
-        .with(cors_filter.clone());
+        });
 
-    let api_route = warp::path("api")
-        .and(health_route.or(search_route));
+    let code_intel_route = warp::path("code_intel")
+        .and(warp::post())
+        .and(warp::body::json())
+        .and(warp::any().map(move || engine_arc.clone()))
+        .and_then(move |request: CodeIntelRequest, engine: Arc<FileSearchEngine>| async move {
+            log::info!("Received code intel request: {}:{}:{}", request.path, request.line, request.column);
+            let start_time = std::time::Instant::now();
+
+            let code_intel = engine.code_intel(CodeIntelOptions {
+                repo: request.repo,
+                path: request.path,
+                line: request.line,
+                column: request.column,
+                limit: request.limit.unwrap_or(1000),
+            }).await;
+            match code_intel {
+                Ok(code_intel) => {
+                    log::info!("Found {} definitions and {} references of {}", code_intel.definitions.len(), code_intel.total_references, code_intel.symbol);
+                    let time_taken = start_time.elapsed().as_millis() as u64;
+                    let response = StandardResponse {
+                        data: Some(CodeIntelResponse {
+                            symbol: code_intel.symbol,
+                            hover: code_intel.hover,
+                            definitions: code_intel.definitions,
+                            references: code_intel.references,
+                            total_references: code_intel.total_references,
+                            time_taken,
+                        }),
+                        error: None,
+                        error_code: None,
+                        error_position: None,
+                        time_taken: Some(time_taken),
+                    };
+                    Ok::<_, warp::Rejection>(warp::reply::with_status(warp::reply::json(&response), StatusCode::OK))
+                }
+                Err(err) => Ok::<_, warp::Rejection>(error_reply("Code intel lookup failed", err, start_time)),
+            }
+        });
 
     // let routes = web_static_route.or(api_route);
-    log::info!("API server running on http://127.0.0.1:3030");
-    warp::serve(api_route)
-        .run(([127, 0, 0, 1], 3030)).await;
+    warp::path("api")
+        .and(health_route.or(status_route).or(search_route).or(references_route).or(code_intel_route))
+        .recover(handle_rejection)
+        .with(cors_filter)
+}
+
+/// Replies with the status code for the kind of error: the request's fault is a 4xx,
+/// the engine's a 5xx.
+fn error_reply(context: &str, err: SearchError, start_time: std::time::Instant) -> warp::reply::WithStatus<warp::reply::Json> {
+    let status = match err {
+        SearchError::InvalidQuery { .. } => StatusCode::BAD_REQUEST,
+        SearchError::NotFound { .. } => StatusCode::NOT_FOUND,
+        SearchError::IndexUnavailable { .. } => StatusCode::SERVICE_UNAVAILABLE,
+        SearchError::Io { .. } | SearchError::Internal { .. } => StatusCode::INTERNAL_SERVER_ERROR,
+    };
+    if status.is_server_error() {
+        log::error!("{}: {:?}", context, err);
+    } else {
+        log::warn!("{}: {}", context, err);
+    }
+    error_reply_with_status(err, status, start_time)
 }
 
+fn error_reply_with_status(err: SearchError, status: StatusCode, start_time: std::time::Instant) -> warp::reply::WithStatus<warp::reply::Json> {
+    let response = StandardResponse::<Vec<u8>> {
+        data: None,
+        error: Some(err.to_string()),
+        error_code: Some(err.get_code().to_string()),
+        error_position: err.position(),
+        time_taken: Some(start_time.elapsed().as_millis() as u64),
+    };
+    warp::reply::with_status(warp::reply::json(&response), status)
+}
+
+/// Turns warp's rejections into error replies: unknown paths are `not_found`, malformed
+/// requests `invalid_query`, keeping warp's status codes for the latter.
+async fn handle_rejection(rejection: Rejection) -> Result<warp::reply::WithStatus<warp::reply::Json>, Infallible> {
+    let start_time = std::time::Instant::now();
+    if rejection.is_not_found() {
+        return Ok(error_reply("Request failed", SearchError::not_found("No such endpoint"), start_time));
+    }
+    let (message, status) = if let Some(err) = rejection.find::<warp::filters::body::BodyDeserializeError>() {
+        (format!("Invalid request body: {}", err), StatusCode::BAD_REQUEST)
+    } else if rejection.find::<warp::reject::MethodNotAllowed>().is_some() {
+        ("Method not allowed".to_string(), StatusCode::METHOD_NOT_ALLOWED)
+    } else if let Some(err) = rejection.find::<warp::reject::UnsupportedMediaType>() {
+        (err.to_string(), StatusCode::UNSUPPORTED_MEDIA_TYPE)
+    } else if let Some(err) = rejection.find::<warp::reject::PayloadTooLarge>() {
+        (err.to_string(), StatusCode::PAYLOAD_TOO_LARGE)
+    } else {
+        log::error!("Unhandled rejection: {:?}", rejection);
+        let err = SearchError::Internal { message: "Unhandled rejection".to_string(), source: None };
+        return Ok(error_reply_with_status(err, StatusCode::INTERNAL_SERVER_ERROR, start_time));
+    };
+    log::warn!("Rejected request: {}", message);
+    Ok(error_reply_with_status(SearchError::invalid_query(message), status, start_time))
+}
+
+/// A references request names the symbol directly or points at it with a full position.
+fn reference_target(request: ReferencesRequest) -> Result<ReferenceTarget, SearchError> {
+    match request {
+        ReferencesRequest { name: Some(name), .. } => Ok(ReferenceTarget::Name(name)),
+        ReferencesRequest { repo: Some(repo), path: Some(path), line: Some(line), column: Some(column), .. } => {
+            Ok(ReferenceTarget::Position { repo, path, line, column })
+        }
+        _ => Err(SearchError::invalid_query("Either a name or a repo, path, line and column are required")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config;
+
+    #[tokio::test]
+    async fn rejected_requests_get_typed_error_bodies() {
+        let repo_dir = tempfile::tempdir().unwrap();
+        let config = config::test_config(repo_dir.path(), "");
+        let routes = api_routes(Arc::new(FileSearchEngine::new(&config).unwrap()));
+        let error_body = |response: &warp::http::Response<warp::hyper::body::Bytes>| {
+            serde_json::from_slice::<serde_json::Value>(response.body()).unwrap()
+        };
+
+        let response = warp::test::request().method("POST").path("/api/search")
+            .header("content-type", "application/json")
+            .body("{\"query\": ")
+            .reply(&routes).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(error_body(&response)["error_code"], "invalid_query");
+
+        let response = warp::test::request().method("GET").path("/api/search").reply(&routes).await;
+        assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+        assert_eq!(error_body(&response)["error_code"], "invalid_query");
+
+        let response = warp::test::request().method("GET").path("/api/unknown").reply(&routes).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        assert_eq!(error_body(&response)["error_code"], "not_found");
+    }
+}
@@ -1,11 +1,17 @@
 use serde::{Deserialize, Serialize};
 
-use crate::search::ResultItem;
+use crate::search::{Facets, FileReferences, Location, ResultItem, SearchMode, SkippedFile, SortBy};
 
 #[derive(Serialize, Deserialize)]
 pub struct StandardResponse<T> where T: Serialize {
     pub(crate) data: Option<T>,
     pub(crate) error: Option<String>,
+    /// The kind of error, like `invalid_query` or `index_unavailable`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) error_code: Option<String>,
+    /// The char offset in the query a syntax error was found at.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) error_position: Option<usize>,
     pub(crate) time_taken: Option<u64>,
 }
 
@@ -13,12 +19,61 @@ pub struct StandardResponse<T> where T: Serialize {
 #[derive(Serialize, Deserialize)]
 pub struct SearchRequest {
     pub query: String,
+    pub mode: Option<SearchMode>,
+    pub sort_by: Option<SortBy>,
     pub limit: Option<usize>,
+    pub offset: Option<usize>,
+    pub cursor: Option<String>,
+    pub include_content: Option<bool>,
+    pub context_lines: Option<usize>,
+    pub explain: Option<bool>,
 }
 
 #[derive(Serialize, Deserialize)]
 pub struct SearchResponse {
     pub results: Vec<ResultItem>,
+    pub total: usize,
+    pub offset: usize,
+    pub next_cursor: Option<String>,
+    pub facets: Facets,
+    pub time_taken: u64,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ReferencesRequest {
+    pub name: Option<String>,
+    pub repo: Option<String>,
+    pub path: Option<String>,
+    pub line: Option<usize>,
+    pub column: Option<usize>,
+    pub limit: Option<usize>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ReferencesResponse {
+    pub name: String,
+    pub files: Vec<FileReferences>,
+    pub total_definitions: usize,
+    pub total_references: usize,
+    pub time_taken: u64,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct CodeIntelRequest {
+    pub repo: String,
+    pub path: String,
+    pub line: usize,
+    pub column: usize,
+    pub limit: Option<usize>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct CodeIntelResponse {
+    pub symbol: String,
+    pub hover: Option<String>,
+    pub definitions: Vec<Location>,
+    pub references: Vec<Location>,
+    pub total_references: usize,
     pub time_taken: u64,
 }
 
@@ -26,4 +81,16 @@ pub struct SearchResponse {
 #[derive(Serialize, Deserialize)]
 pub struct HealthCheckResponse {
     pub status: String,
-}
\ No newline at end of file
+}
+
+#[derive(Serialize)]
+pub struct IndexStatusResponse {
+    pub repos: Vec<RepoIndexStatus>,
+}
+
+/// The files the last indexing run of the repo left out, like binary or too large files.
+#[derive(Serialize)]
+pub struct RepoIndexStatus {
+    pub name: String,
+    pub skipped_files: Vec<SkippedFile>,
+}
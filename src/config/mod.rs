@@ -1,7 +1,7 @@
 use std::fs;
+use std::path::{Path, PathBuf};
 
-use serde::Deserialize;
-use serde_yaml;
+use serde::{Deserialize, Deserializer};
 
 pub(crate) fn load_config(file_path: &str) -> Result<Config, serde_yaml::Error> {
     let contents = fs::read_to_string(file_path)
@@ -9,6 +9,9 @@ pub(crate) fn load_config(file_path: &str) -> Result<Config, serde_yaml::Error>
     let config: Config = serde_yaml::from_str(&contents)?;
     config.validate()
         .expect("Invalid config");
+    for repo in &config.repos {
+        repo.warn_deprecated_settings();
+    }
     Ok(config)
 }
 
@@ -20,6 +23,10 @@ trait Validatable {
 pub(crate) struct Config {
     pub(crate) repos: Vec<Repo>,
     pub(crate) indexer: Indexer,
+    #[serde(default)]
+    pub(crate) watcher: Watcher,
+    #[serde(default)]
+    pub(crate) ranking: Ranking,
 }
 
 impl Validatable for Config {
@@ -30,6 +37,8 @@ impl Validatable for Config {
         for repo in &self.repos {
             repo.validate()?;
         }
+        self.indexer.validate()?;
+        self.ranking.validate()?;
         Ok(())
     }
 }
@@ -40,8 +49,98 @@ pub struct Repo {
     #[serde(rename = "type")]
     pub(crate) type_: String,
     pub(crate) path: String,
+    #[serde(rename = "ref", default)]
+    pub(crate) ref_: Option<String>,
+    #[serde(default)]
+    pub(crate) branches: Vec<String>,
+    #[serde(default)]
+    pub(crate) include_patterns: Vec<String>,
+    #[serde(default)]
+    pub(crate) exclude_patterns: Vec<String>,
+    /// Deprecated, use `exclude_patterns`.
+    #[serde(default)]
     pub(crate) skip_patterns: Vec<String>,
+    /// Deprecated, use `include_patterns` like `*.rs`.
+    #[serde(default)]
     pub(crate) allowed_file_extensions: Vec<String>,
+    #[serde(default = "default_respect_ignore_files")]
+    pub(crate) respect_ignore_files: bool,
+    #[serde(default, deserialize_with = "deserialize_byte_size")]
+    pub(crate) max_file_size: Option<u64>,
+    #[serde(default)]
+    pub(crate) large_files: Option<LargeFilePolicy>,
+    #[serde(default)]
+    pub(crate) precise_index: Option<PreciseIndex>,
+}
+
+/// A SCIP or LSIF dump produced by a language's own indexer, imported for precise
+/// definitions, references and hover text. Relative paths are resolved against the repo path.
+#[derive(Debug, Deserialize, Clone)]
+pub struct PreciseIndex {
+    pub(crate) format: PreciseIndexFormat,
+    pub(crate) path: String,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum PreciseIndexFormat {
+    Scip,
+    Lsif,
+}
+
+fn default_respect_ignore_files() -> bool {
+    true
+}
+
+impl Repo {
+    /// The size limit for files of this repo, the repo settings override the indexer ones.
+    pub(crate) fn file_size_limit(&self, indexer: &Indexer) -> (Option<u64>, LargeFilePolicy) {
+        (
+            self.max_file_size.or(indexer.max_file_size),
+            self.large_files.unwrap_or(indexer.large_files),
+        )
+    }
+
+    /// The include and exclude globs of the repo. `!` prefixed include patterns are
+    /// excludes, and the older `allowed_file_extensions` and `skip_patterns` settings are
+    /// translated to their glob equivalents.
+    pub(crate) fn file_patterns(&self) -> (Vec<String>, Vec<String>) {
+        let mut include_patterns = Vec::new();
+        let mut exclude_patterns = self.exclude_patterns.clone();
+        for pattern in &self.include_patterns {
+            match pattern.strip_prefix('!') {
+                Some(exclude_pattern) => exclude_patterns.push(exclude_pattern.to_string()),
+                None => include_patterns.push(pattern.clone()),
+            }
+        }
+        include_patterns.extend(self.allowed_file_extensions.iter().map(|ext| format!("*.{}", ext)));
+        exclude_patterns.extend(self.skip_patterns.iter().cloned());
+        (include_patterns, exclude_patterns)
+    }
+
+    fn warn_deprecated_settings(&self) {
+        if !self.allowed_file_extensions.is_empty() {
+            log::warn!("Repo {}: allowed_file_extensions is deprecated, use include_patterns like \"*.{}\" instead", self.name, self.allowed_file_extensions[0]);
+        }
+        if !self.skip_patterns.is_empty() {
+            log::warn!("Repo {}: skip_patterns is deprecated, use exclude_patterns instead", self.name);
+        }
+    }
+
+    /// The location of the repo's precise index dump, if it has one.
+    pub(crate) fn precise_index_path(&self) -> Option<PathBuf> {
+        self.precise_index.as_ref()
+            .map(|precise_index| Path::new(&self.path).join(&precise_index.path))
+    }
+
+    /// The branches or tags to index, `ref` (or HEAD) when no branches are listed.
+    pub(crate) fn git_refs(&self) -> Vec<&str> {
+        if self.branches.is_empty() {
+            vec![self.ref_.as_deref().unwrap_or("HEAD")]
+        } else {
+            self.branches.iter().map(|branch| branch.as_str()).collect()
+        }
+    }
 }
 
 impl Validatable for Repo {
@@ -53,14 +152,37 @@ impl Validatable for Repo {
         if self.path.is_empty() {
             return Err("Repo path is empty".to_string());
         }
-        if self.type_ != "fs" {
-            return Err("Repo type is not fs".to_string());
+        if self.type_ != "fs" && self.type_ != "git" {
+            return Err("Repo type is not fs or git".to_string());
+        }
+
+        if self.ref_.is_some() && self.type_ != "git" {
+            return Err("Repo ref is only supported for git repos".to_string());
+        }
+
+        if !self.branches.is_empty() && self.type_ != "git" {
+            return Err("Repo branches are only supported for git repos".to_string());
+        }
+
+        if !self.branches.is_empty() && self.ref_.is_some() {
+            return Err("Repo ref and branches cannot be used together".to_string());
         }
 
         if self.type_.is_empty() {
             return Err("Repo type is empty".to_string());
         }
 
+        let (include_patterns, exclude_patterns) = self.file_patterns();
+        for pattern in include_patterns.iter().chain(exclude_patterns.iter()) {
+            if let Err(err) = globset::Glob::new(pattern.trim_start_matches('/')) {
+                return Err(format!("Invalid file pattern in repo {}: {}", self.name, err));
+            }
+        }
+
+        if self.precise_index.as_ref().is_some_and(|precise_index| precise_index.path.is_empty()) {
+            return Err(format!("Precise index path of repo {} is empty", self.name));
+        }
+
         Ok(())
     }
 }
@@ -71,6 +193,120 @@ pub struct Indexer {
     pub(crate) use_temporary_index: bool,
     pub(crate) index_path: Option<String>,
     pub(crate) force_reindex: bool,
+    #[serde(default)]
+    pub(crate) incremental_reindex: bool,
+    #[serde(default)]
+    pub(crate) binary_files: BinaryFilePolicy,
+    #[serde(default, deserialize_with = "deserialize_byte_size")]
+    pub(crate) max_file_size: Option<u64>,
+    #[serde(default)]
+    pub(crate) large_files: LargeFilePolicy,
+    #[serde(default)]
+    pub(crate) chunking: Chunking,
+    #[serde(default)]
+    pub(crate) tokenizers: Tokenizers,
+}
+
+/// The tokenizer of each searchable field. Changing them rebuilds the index on the next start.
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct Tokenizers {
+    #[serde(default)]
+    pub(crate) file_content: FieldTokenizer,
+    #[serde(default)]
+    pub(crate) file_name: FieldTokenizer,
+    #[serde(default)]
+    pub(crate) file_path: FieldTokenizer,
+}
+
+/// `code` splits identifiers into their camelCase and snake_case parts, `default` is
+/// tantivy's word tokenizer, and `raw` indexes the whole value as a single token.
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum FieldTokenizer {
+    #[default]
+    Code,
+    Default,
+    Raw,
+}
+
+impl FieldTokenizer {
+    pub(crate) fn get_name(&self) -> &str {
+        match self {
+            FieldTokenizer::Code => crate::search::CODE_TOKENIZER_NAME,
+            FieldTokenizer::Default => "default",
+            FieldTokenizer::Raw => "raw",
+        }
+    }
+}
+
+/// Files longer than `lines` are also indexed as windows of `lines` lines, each sharing
+/// `overlap` lines with the previous one, so matches can be located within the file.
+/// Setting `lines` to 0 turns chunking off.
+#[derive(Debug, Deserialize, Clone)]
+pub struct Chunking {
+    #[serde(default = "default_chunk_lines")]
+    pub(crate) lines: usize,
+    #[serde(default = "default_chunk_overlap")]
+    pub(crate) overlap: usize,
+}
+
+fn default_chunk_lines() -> usize {
+    50
+}
+
+fn default_chunk_overlap() -> usize {
+    10
+}
+
+impl Default for Chunking {
+    fn default() -> Self {
+        Chunking {
+            lines: default_chunk_lines(),
+            overlap: default_chunk_overlap(),
+        }
+    }
+}
+
+/// What to do with files above `max_file_size`: leave them out, index their first
+/// `max_file_size` bytes, or index their name, path and size without content.
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum LargeFilePolicy {
+    #[default]
+    Skip,
+    Truncate,
+    MetadataOnly,
+}
+
+/// Accepts sizes as a plain number of bytes or as a string with a unit, like `10mb`.
+fn deserialize_byte_size<'de, D>(deserializer: D) -> Result<Option<u64>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum ByteSize {
+        Bytes(u64),
+        Text(String),
+    }
+
+    match Option::<ByteSize>::deserialize(deserializer)? {
+        None => Ok(None),
+        Some(ByteSize::Bytes(bytes)) => Ok(Some(bytes)),
+        Some(ByteSize::Text(text)) => crate::utils::parse_byte_size(&text)
+            .map(Some)
+            .ok_or_else(|| serde::de::Error::custom(format!("invalid byte size: {}", text))),
+    }
+}
+
+/// What to do with files that look binary: leave them out, or index their name and path
+/// with empty content.
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum BinaryFilePolicy {
+    #[default]
+    Skip,
+    NameOnly,
 }
 
 impl Validatable for Indexer {
@@ -81,6 +317,175 @@ impl Validatable for Indexer {
         if self.index_path.is_none() && !self.use_temporary_index {
             return Err("Must specify index path or use temporary index".to_string());
         }
+        if self.chunking.lines > 0 && self.chunking.overlap >= self.chunking.lines {
+            return Err("Chunk overlap must be smaller than the chunk lines".to_string());
+        }
         Ok(())
     }
-}
\ No newline at end of file
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct Watcher {
+    #[serde(default)]
+    pub(crate) enabled: bool,
+    #[serde(default = "default_watcher_debounce_ms")]
+    pub(crate) debounce_ms: u64,
+    #[serde(default = "default_watcher_max_batch_delay_ms")]
+    pub(crate) max_batch_delay_ms: u64,
+}
+
+fn default_watcher_debounce_ms() -> u64 {
+    500
+}
+
+fn default_watcher_max_batch_delay_ms() -> u64 {
+    5_000
+}
+
+impl Default for Watcher {
+    fn default() -> Self {
+        Watcher {
+            enabled: false,
+            debounce_ms: default_watcher_debounce_ms(),
+            max_batch_delay_ms: default_watcher_max_batch_delay_ms(),
+        }
+    }
+}
+
+/// How search scores are adjusted on top of BM25. Matches in the file name weigh more than
+/// matches in the path, which weigh more than content matches. Files matching
+/// `demoted_patterns`, like tests, vendored and generated code, have their score multiplied
+/// by `demotion`, and recently modified files get up to `recency_boost` times their score on
+/// top, an advantage that halves every `recency_half_life_days`.
+#[derive(Debug, Deserialize, Clone)]
+pub struct Ranking {
+    #[serde(default = "default_file_name_boost")]
+    pub(crate) file_name_boost: f32,
+    #[serde(default = "default_file_path_boost")]
+    pub(crate) file_path_boost: f32,
+    #[serde(default = "default_content_boost")]
+    pub(crate) content_boost: f32,
+    #[serde(default = "default_demoted_patterns")]
+    pub(crate) demoted_patterns: Vec<String>,
+    #[serde(default = "default_demotion")]
+    pub(crate) demotion: f32,
+    #[serde(default = "default_recency_boost")]
+    pub(crate) recency_boost: f32,
+    #[serde(default = "default_recency_half_life_days")]
+    pub(crate) recency_half_life_days: f32,
+}
+
+fn default_file_name_boost() -> f32 {
+    3.0
+}
+
+fn default_file_path_boost() -> f32 {
+    2.0
+}
+
+fn default_content_boost() -> f32 {
+    1.0
+}
+
+fn default_demoted_patterns() -> Vec<String> {
+    [
+        "**/test/**", "**/tests/**", "**/__tests__/**", "**/testdata/**", "**/fixtures/**",
+        "**/*_test.*", "**/*.test.*", "**/*.spec.*", "**/test_*.py",
+        "**/vendor/**", "**/third_party/**", "**/node_modules/**",
+        "**/generated/**", "**/*.generated.*", "**/*.pb.go", "**/*_pb2.py", "**/*.min.js",
+    ].iter().map(|pattern| pattern.to_string()).collect()
+}
+
+fn default_demotion() -> f32 {
+    0.5
+}
+
+fn default_recency_boost() -> f32 {
+    0.2
+}
+
+fn default_recency_half_life_days() -> f32 {
+    90.0
+}
+
+impl Default for Ranking {
+    fn default() -> Self {
+        Ranking {
+            file_name_boost: default_file_name_boost(),
+            file_path_boost: default_file_path_boost(),
+            content_boost: default_content_boost(),
+            demoted_patterns: default_demoted_patterns(),
+            demotion: default_demotion(),
+            recency_boost: default_recency_boost(),
+            recency_half_life_days: default_recency_half_life_days(),
+        }
+    }
+}
+
+impl Validatable for Ranking {
+    fn validate(&self) -> Result<(), String> {
+        if self.file_name_boost < 0.0 || self.file_path_boost < 0.0 || self.content_boost < 0.0 {
+            return Err("Ranking boosts cannot be negative".to_string());
+        }
+        if !(0.0..=1.0).contains(&self.demotion) {
+            return Err("Ranking demotion must be between 0 and 1".to_string());
+        }
+        if self.recency_boost < 0.0 {
+            return Err("Ranking recency boost cannot be negative".to_string());
+        }
+        if self.recency_half_life_days <= 0.0 {
+            return Err("Ranking recency half life must be positive".to_string());
+        }
+        for pattern in &self.demoted_patterns {
+            if let Err(err) = globset::Glob::new(pattern) {
+                return Err(format!("Invalid demoted pattern: {}", err));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A config indexing the fs repo `test` at `repo_path` into a temporary index, with the YAML
+/// `overrides` merged over it. A `repo` key in the overrides applies to that repo.
+#[cfg(test)]
+pub(crate) fn test_config(repo_path: &Path, overrides: &str) -> Config {
+    let mut config: serde_yaml::Value = serde_yaml::from_str(&format!(r#"
+repos:
+  - name: "test"
+    type: "fs"
+    path: "{}/"
+    include_patterns: ["*.rs"]
+indexer:
+  use_temporary_index: true
+  force_reindex: false
+  incremental_reindex: true
+"#, repo_path.display())).unwrap();
+    let mut overrides: serde_yaml::Value = serde_yaml::from_str(overrides).unwrap();
+    if let Some(repo) = overrides.as_mapping_mut().and_then(|overrides| overrides.remove("repo")) {
+        merge_yaml(&mut config["repos"][0], repo);
+    }
+    if !overrides.is_null() {
+        merge_yaml(&mut config, overrides);
+    }
+    let config: Config = serde_yaml::from_value(config).unwrap();
+    config.validate().unwrap();
+    config
+}
+
+/// Merges mappings key by key, anything else in `overrides` replaces the base value.
+#[cfg(test)]
+fn merge_yaml(base: &mut serde_yaml::Value, overrides: serde_yaml::Value) {
+    match (base, overrides) {
+        (serde_yaml::Value::Mapping(base), serde_yaml::Value::Mapping(overrides)) => {
+            for (key, value) in overrides {
+                match base.get_mut(&key) {
+                    Some(base_value) => merge_yaml(base_value, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, overrides) => *base = overrides,
+    }
+}
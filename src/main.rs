@@ -1,7 +1,10 @@
+use std::sync::Arc;
+
 mod search;
 mod utils;
 mod config;
 mod api;
+mod watcher;
 
 #[tokio::main]
 async fn main() {
@@ -10,5 +13,12 @@ async fn main() {
     let config = config::load_config("config.yaml").unwrap();
     let engine = search::FileSearchEngine::new(&config).unwrap();
     let _ = engine.initialize().await;
+
+    let engine = Arc::new(engine);
+    let _watcher = if config.watcher.enabled {
+        Some(watcher::start_watcher(engine.clone()).expect("Failed to start filesystem watcher"))
+    } else {
+        None
+    };
     api::start_api(engine).await;
 }
\ No newline at end of file
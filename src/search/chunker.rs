@@ -0,0 +1,37 @@
+use crate::config::Chunking;
+
+/// A window of consecutive lines of a file, with 1-based inclusive line numbers.
+pub(crate) struct LineChunk {
+    pub(crate) start_line: usize,
+    pub(crate) end_line: usize,
+    pub(crate) content: String,
+}
+
+/// Splits file content into overlapping line windows. Files that fit in a single chunk are
+/// not split, their file document already covers them.
+pub(crate) fn split_into_chunks(content: &str, chunking: &Chunking) -> Vec<LineChunk> {
+    if chunking.lines == 0 {
+        return vec![];
+    }
+    let lines = content.lines().collect::<Vec<_>>();
+    if lines.len() <= chunking.lines {
+        return vec![];
+    }
+
+    let step = chunking.lines - chunking.overlap;
+    let mut chunks = Vec::new();
+    let mut start = 0;
+    loop {
+        let end = (start + chunking.lines).min(lines.len());
+        chunks.push(LineChunk {
+            start_line: start + 1,
+            end_line: end,
+            content: lines[start..end].join("\n"),
+        });
+        if end == lines.len() {
+            break;
+        }
+        start += step;
+    }
+    chunks
+}
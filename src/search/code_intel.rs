@@ -0,0 +1,479 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use prost::Message;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::search::search_error::SearchError;
+
+/// What to look up: the symbol at a 1-based line and column of an indexed file.
+pub struct CodeIntelOptions {
+    pub repo: String,
+    pub path: String,
+    pub line: usize,
+    pub column: usize,
+    /// How many references are returned at most.
+    pub limit: usize,
+}
+
+/// Precise navigation for a symbol, from the precise index dumps of the repos.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CodeIntel {
+    pub symbol: String,
+    pub hover: Option<String>,
+    pub definitions: Vec<Location>,
+    pub references: Vec<Location>,
+    pub total_references: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Location {
+    pub repo_name: String,
+    pub file_path: String,
+    pub range: SourceRange,
+    pub is_definition: bool,
+}
+
+/// A range of a file with 1-based lines and char columns, the end column is exclusive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct SourceRange {
+    pub start_line: usize,
+    pub start_column: usize,
+    pub end_line: usize,
+    pub end_column: usize,
+}
+
+impl SourceRange {
+    /// Converts the 0-based lines and characters used by SCIP and LSIF.
+    fn from_zero_based(start_line: i64, start_column: i64, end_line: i64, end_column: i64) -> Self {
+        SourceRange {
+            start_line: start_line.max(0) as usize + 1,
+            start_column: start_column.max(0) as usize + 1,
+            end_line: end_line.max(0) as usize + 1,
+            end_column: end_column.max(0) as usize + 1,
+        }
+    }
+
+    pub(crate) fn contains(&self, line: usize, column: usize) -> bool {
+        (self.start_line, self.start_column) <= (line, column) && (line, column) < (self.end_line, self.end_column)
+    }
+}
+
+/// A symbol occurring in a file. Symbols are SCIP symbols or LSIF monikers, and names of
+/// symbols local to a file or dump are qualified with the repo so they never clash.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Occurrence {
+    pub(crate) symbol: String,
+    pub(crate) range: SourceRange,
+    pub(crate) is_definition: bool,
+}
+
+impl Occurrence {
+    /// Encodes the occurrence for the stored `precise_occurrences` field, as
+    /// `start_line:start_column:end_line:end_column:definition:symbol`.
+    pub(crate) fn encode(&self) -> String {
+        let range = self.range;
+        format!(
+            "{}:{}:{}:{}:{}:{}",
+            range.start_line, range.start_column, range.end_line, range.end_column, self.is_definition as u8, self.symbol,
+        )
+    }
+
+    pub(crate) fn decode(value: &str) -> Option<Self> {
+        let mut parts = value.splitn(6, ':');
+        let mut number = || parts.next()?.parse::<usize>().ok();
+        let range = SourceRange {
+            start_line: number()?,
+            start_column: number()?,
+            end_line: number()?,
+            end_column: number()?,
+        };
+        let is_definition = number()? == 1;
+        Some(Occurrence {
+            symbol: parts.next()?.to_string(),
+            range,
+            is_definition,
+        })
+    }
+}
+
+/// The hover text of a symbol, usually its signature and documentation as markdown.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct SymbolHover {
+    pub(crate) symbol: String,
+    pub(crate) text: String,
+}
+
+/// The precise data of a single file of a dump, keyed by its path relative to the repo.
+#[derive(Debug, Default)]
+pub(crate) struct PreciseDocument {
+    pub(crate) file_path: String,
+    pub(crate) occurrences: Vec<Occurrence>,
+    pub(crate) hovers: Vec<SymbolHover>,
+}
+
+impl PreciseDocument {
+    /// The innermost occurrence covering the position.
+    pub(crate) fn occurrence_at(&self, line: usize, column: usize) -> Option<&Occurrence> {
+        self.occurrences.iter()
+            .filter(|occurrence| occurrence.range.contains(line, column))
+            .min_by_key(|occurrence| (occurrence.range.end_line - occurrence.range.start_line, occurrence.range.end_column as i64 - occurrence.range.start_column as i64))
+    }
+}
+
+fn local_symbol(repo_name: &str, scope: &str, id: &str) -> String {
+    format!("local {}/{}#{}", repo_name, scope, id)
+}
+
+/// The subset of the SCIP protobuf schema needed for navigation, other fields are skipped
+/// while decoding.
+#[derive(Clone, PartialEq, Message)]
+pub(crate) struct ScipIndex {
+    #[prost(message, repeated, tag = "2")]
+    pub(crate) documents: Vec<ScipDocument>,
+    #[prost(message, repeated, tag = "3")]
+    pub(crate) external_symbols: Vec<ScipSymbolInformation>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub(crate) struct ScipDocument {
+    #[prost(string, tag = "1")]
+    pub(crate) relative_path: String,
+    #[prost(message, repeated, tag = "2")]
+    pub(crate) occurrences: Vec<ScipOccurrence>,
+    #[prost(message, repeated, tag = "3")]
+    pub(crate) symbols: Vec<ScipSymbolInformation>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub(crate) struct ScipOccurrence {
+    /// `[start_line, start_character, end_character]` or `[start_line, start_character,
+    /// end_line, end_character]`, all 0-based.
+    #[prost(int32, repeated, tag = "1")]
+    pub(crate) range: Vec<i32>,
+    #[prost(string, tag = "2")]
+    pub(crate) symbol: String,
+    #[prost(int32, tag = "3")]
+    pub(crate) symbol_roles: i32,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub(crate) struct ScipSymbolInformation {
+    #[prost(string, tag = "1")]
+    pub(crate) symbol: String,
+    #[prost(string, repeated, tag = "3")]
+    pub(crate) documentation: Vec<String>,
+}
+
+const SCIP_DEFINITION_ROLE: i32 = 0x1;
+
+/// Reads a SCIP index. Symbols from dependencies are documented in the external symbols,
+/// their hover text is kept with the files referencing them.
+pub(crate) fn read_scip(repo_name: &str, bytes: &[u8]) -> Result<Vec<PreciseDocument>, SearchError> {
+    let index = ScipIndex::decode(bytes)
+        .map_err(|err| SearchError::internal(format!("Invalid SCIP index: {}", err), err))?;
+    let external_hovers = index.external_symbols.iter()
+        .filter_map(scip_hover)
+        .map(|hover| (hover.symbol.clone(), hover))
+        .collect::<HashMap<_, _>>();
+
+    let mut documents = Vec::with_capacity(index.documents.len());
+    for scip_document in index.documents {
+        let file_path = scip_document.relative_path;
+        let qualify = |symbol: &str| match symbol.strip_prefix("local ") {
+            Some(id) => local_symbol(repo_name, &file_path, id),
+            None => symbol.to_string(),
+        };
+        let occurrences = scip_document.occurrences.iter()
+            .filter(|occurrence| !occurrence.symbol.is_empty())
+            .filter_map(|occurrence| {
+                let range = match occurrence.range[..] {
+                    [start_line, start_column, end_column] => (start_line, start_column, start_line, end_column),
+                    [start_line, start_column, end_line, end_column] => (start_line, start_column, end_line, end_column),
+                    _ => return None,
+                };
+                Some(Occurrence {
+                    symbol: qualify(&occurrence.symbol),
+                    range: SourceRange::from_zero_based(range.0 as i64, range.1 as i64, range.2 as i64, range.3 as i64),
+                    is_definition: occurrence.symbol_roles & SCIP_DEFINITION_ROLE != 0,
+                })
+            })
+            .collect::<Vec<_>>();
+        let mut hovers = scip_document.symbols.iter()
+            .filter_map(scip_hover)
+            .map(|hover| SymbolHover { symbol: qualify(&hover.symbol), ..hover })
+            .collect::<Vec<_>>();
+        let referenced_symbols = scip_document.occurrences.iter()
+            .map(|occurrence| occurrence.symbol.as_str())
+            .collect::<HashSet<_>>();
+        hovers.extend(referenced_symbols.into_iter().filter_map(|symbol| external_hovers.get(symbol).cloned()));
+        documents.push(PreciseDocument {
+            file_path,
+            occurrences,
+            hovers,
+        });
+    }
+    Ok(documents)
+}
+
+fn scip_hover(symbol_information: &ScipSymbolInformation) -> Option<SymbolHover> {
+    if symbol_information.documentation.is_empty() {
+        return None;
+    }
+    Some(SymbolHover {
+        symbol: symbol_information.symbol.clone(),
+        text: symbol_information.documentation.join("\n\n"),
+    })
+}
+
+/// The vertices and edges of an LSIF dump that navigation needs, by element id.
+#[derive(Default)]
+struct LsifGraph {
+    project_root: Option<String>,
+    documents: BTreeMap<String, String>,
+    ranges: HashMap<String, SourceRange>,
+    contains: HashMap<String, Vec<String>>,
+    next: HashMap<String, String>,
+    definition_results: HashMap<String, String>,
+    hover_results: HashMap<String, String>,
+    monikers: HashMap<String, String>,
+    items: HashMap<String, HashSet<String>>,
+    hover_texts: HashMap<String, String>,
+    moniker_symbols: HashMap<String, String>,
+}
+
+impl LsifGraph {
+    /// The element followed by the result sets it is linked to through `next` edges.
+    fn chain<'a>(&'a self, id: &'a str) -> Vec<&'a str> {
+        let mut chain = vec![id];
+        while let Some(next) = self.next.get(*chain.last().unwrap()) {
+            if chain.contains(&next.as_str()) {
+                break;
+            }
+            chain.push(next);
+        }
+        chain
+    }
+
+    fn first_in_chain<'a>(&'a self, chain: &[&str], edges: &'a HashMap<String, String>) -> Option<&'a String> {
+        chain.iter().find_map(|id| edges.get(*id))
+    }
+
+    fn relative_path(&self, uri: &str, repo_path: &str) -> String {
+        let path = percent_decode(uri.strip_prefix("file://").unwrap_or(uri));
+        let root = self.project_root.as_deref()
+            .map(|root| percent_decode(root.strip_prefix("file://").unwrap_or(root)))
+            .unwrap_or_else(|| repo_path.to_string());
+        path.strip_prefix(root.trim_end_matches('/'))
+            .map(|path| path.trim_start_matches('/').to_string())
+            .unwrap_or(path)
+    }
+}
+
+fn element_id(value: &Value) -> Option<String> {
+    match value {
+        Value::String(id) => Some(id.clone()),
+        Value::Number(id) => Some(id.to_string()),
+        _ => None,
+    }
+}
+
+fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        let hex = bytes.get(index + 1..index + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[index], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                index += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                index += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).to_string()
+}
+
+/// The text of an LSP hover, whose contents are markup, a marked string, or a list of them.
+fn hover_text(contents: &Value) -> String {
+    match contents {
+        Value::String(text) => text.clone(),
+        Value::Array(parts) => parts.iter().map(hover_text).collect::<Vec<_>>().join("\n\n"),
+        Value::Object(object) => {
+            let value = object.get("value").and_then(Value::as_str).unwrap_or_default();
+            match object.get("language").and_then(Value::as_str) {
+                Some(language) => format!("```{}\n{}\n```", language, value),
+                None => value.to_string(),
+            }
+        }
+        _ => String::new(),
+    }
+}
+
+/// Reads an LSIF dump, either as JSON lines or as a single JSON array. Ranges are linked
+/// to symbols through their result sets: a symbol is the moniker of the result set, or
+/// the result set itself when it has none.
+pub(crate) fn read_lsif(repo_name: &str, repo_path: &str, content: &str) -> Result<Vec<PreciseDocument>, SearchError> {
+    let elements: Vec<Value> = if content.trim_start().starts_with('[') {
+        serde_json::from_str(content)
+    } else {
+        content.lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(serde_json::from_str)
+            .collect()
+    }.map_err(|err| SearchError::internal(format!("Invalid LSIF dump: {}", err), err))?;
+
+    let mut graph = LsifGraph::default();
+    for element in &elements {
+        let Some(id) = element.get("id").and_then(element_id) else {
+            continue;
+        };
+        let label = element.get("label").and_then(Value::as_str).unwrap_or_default();
+        let out_v = element.get("outV").and_then(element_id);
+        let in_vs = match (element.get("inV").and_then(element_id), element.get("inVs").and_then(Value::as_array)) {
+            (Some(in_v), _) => vec![in_v],
+            (None, Some(in_vs)) => in_vs.iter().filter_map(element_id).collect(),
+            (None, None) => vec![],
+        };
+        match (element.get("type").and_then(Value::as_str), label) {
+            (Some("vertex"), "metaData") => {
+                graph.project_root = element.get("projectRoot").and_then(Value::as_str).map(str::to_string);
+            }
+            (Some("vertex"), "document") => {
+                if let Some(uri) = element.get("uri").and_then(Value::as_str) {
+                    graph.documents.insert(id, uri.to_string());
+                }
+            }
+            (Some("vertex"), "range") => {
+                let position = |key: &str, part: &str| element.get(key)
+                    .and_then(|position| position.get(part))
+                    .and_then(Value::as_i64)
+                    .unwrap_or_default();
+                graph.ranges.insert(id, SourceRange::from_zero_based(
+                    position("start", "line"), position("start", "character"),
+                    position("end", "line"), position("end", "character"),
+                ));
+            }
+            (Some("vertex"), "hoverResult") => {
+                let contents = element.get("result").and_then(|result| result.get("contents"));
+                graph.hover_texts.insert(id, contents.map(hover_text).unwrap_or_default());
+            }
+            (Some("vertex"), "moniker") => {
+                let scheme = element.get("scheme").and_then(Value::as_str).unwrap_or_default();
+                let identifier = element.get("identifier").and_then(Value::as_str).unwrap_or_default();
+                if element.get("kind").and_then(Value::as_str) != Some("local") && !identifier.is_empty() {
+                    graph.moniker_symbols.insert(id, format!("{} {}", scheme, identifier));
+                }
+            }
+            (Some("edge"), _) => {
+                let Some(out_v) = out_v else {
+                    continue;
+                };
+                let in_v = in_vs.first().cloned();
+                match (label, in_v) {
+                    ("contains", _) => graph.contains.entry(out_v).or_default().extend(in_vs),
+                    ("item", _) => graph.items.entry(out_v).or_default().extend(in_vs),
+                    ("next", Some(in_v)) => {
+                        graph.next.insert(out_v, in_v);
+                    }
+                    ("textDocument/definition", Some(in_v)) => {
+                        graph.definition_results.insert(out_v, in_v);
+                    }
+                    ("textDocument/hover", Some(in_v)) => {
+                        graph.hover_results.insert(out_v, in_v);
+                    }
+                    ("moniker", Some(in_v)) => {
+                        graph.monikers.insert(out_v, in_v);
+                    }
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let mut documents = Vec::with_capacity(graph.documents.len());
+    for (document_id, uri) in &graph.documents {
+        let mut document = PreciseDocument {
+            file_path: graph.relative_path(uri, repo_path),
+            ..Default::default()
+        };
+        let mut hovered_symbols = HashSet::new();
+        for range_id in graph.contains.get(document_id).into_iter().flatten() {
+            let Some(range) = graph.ranges.get(range_id) else {
+                continue;
+            };
+            let chain = graph.chain(range_id);
+            let symbol = graph.first_in_chain(&chain, &graph.monikers)
+                .and_then(|moniker_id| graph.moniker_symbols.get(moniker_id))
+                .cloned()
+                .unwrap_or_else(|| local_symbol(repo_name, "lsif", chain.last().unwrap()));
+            let is_definition = graph.first_in_chain(&chain, &graph.definition_results)
+                .and_then(|result_id| graph.items.get(result_id))
+                .is_some_and(|definition_ranges| definition_ranges.contains(range_id));
+            let hover = graph.first_in_chain(&chain, &graph.hover_results)
+                .and_then(|result_id| graph.hover_texts.get(result_id))
+                .filter(|text| !text.is_empty());
+            if let Some(text) = hover {
+                if hovered_symbols.insert(symbol.clone()) {
+                    document.hovers.push(SymbolHover { symbol: symbol.clone(), text: text.clone() });
+                }
+            }
+            document.occurrences.push(Occurrence { symbol, range: *range, is_definition });
+        }
+        document.occurrences.sort_by_key(|occurrence| occurrence.range);
+        documents.push(document);
+    }
+    Ok(documents)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_lsif_ranges_definitions_and_hovers() {
+        let dump = r#"
+{"id":1,"type":"vertex","label":"metaData","version":"0.4.3","projectRoot":"file:///work/my%20repo"}
+{"id":2,"type":"vertex","label":"document","uri":"file:///work/my%20repo/src/lib.ts","languageId":"typescript"}
+{"id":3,"type":"vertex","label":"resultSet"}
+{"id":4,"type":"vertex","label":"range","start":{"line":0,"character":9},"end":{"line":0,"character":14}}
+{"id":5,"type":"vertex","label":"range","start":{"line":3,"character":2},"end":{"line":3,"character":7}}
+{"id":6,"type":"edge","label":"next","outV":4,"inV":3}
+{"id":7,"type":"edge","label":"next","outV":5,"inV":3}
+{"id":8,"type":"vertex","label":"definitionResult"}
+{"id":9,"type":"edge","label":"textDocument/definition","outV":3,"inV":8}
+{"id":10,"type":"edge","label":"item","outV":8,"inVs":[4],"document":2}
+{"id":11,"type":"vertex","label":"hoverResult","result":{"contents":[{"language":"typescript","value":"function hello(): void"},"Says hello"]}}
+{"id":12,"type":"edge","label":"textDocument/hover","outV":3,"inV":11}
+{"id":13,"type":"edge","label":"contains","outV":2,"inVs":[4,5]}
+"#;
+        let documents = read_lsif("test", "/elsewhere", dump).unwrap();
+        assert_eq!(documents.len(), 1);
+        assert_eq!(documents[0].file_path, "src/lib.ts");
+        assert_eq!(documents[0].occurrences, vec![
+            Occurrence {
+                symbol: "local test/lsif#3".to_string(),
+                range: SourceRange { start_line: 1, start_column: 10, end_line: 1, end_column: 15 },
+                is_definition: true,
+            },
+            Occurrence {
+                symbol: "local test/lsif#3".to_string(),
+                range: SourceRange { start_line: 4, start_column: 3, end_line: 4, end_column: 8 },
+                is_definition: false,
+            },
+        ]);
+        assert_eq!(documents[0].hovers[0].text, "```typescript\nfunction hello(): void\n```\n\nSays hello");
+        assert_eq!(documents[0].occurrence_at(4, 7).map(|occurrence| occurrence.is_definition), Some(false));
+        assert_eq!(documents[0].occurrence_at(4, 8), None);
+
+        let encoded = documents[0].occurrences[0].encode();
+        assert_eq!(Occurrence::decode(&encoded).as_ref(), Some(&documents[0].occurrences[0]));
+    }
+}
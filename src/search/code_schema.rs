@@ -1,9 +1,26 @@
+use std::collections::HashSet;
+
 use serde::{Deserialize, Serialize};
 use tantivy::doc;
-use tantivy::schema::{Field, STORED, STRING, TEXT, TextOptions};
+use tantivy::DateTimePrecision;
+use tantivy::schema::{BytesOptions, DateOptions, Facet, FacetOptions, FAST, Field, INDEXED, IndexRecordOption, NumericOptions, STORED, STRING, TEXT, TextFieldIndexing, TextOptions};
+use tantivy::TantivyError;
+
+use crate::config::{FieldTokenizer, Tokenizers};
+use crate::search::chunker::LineChunk;
+use crate::search::code_intel::{Occurrence, PreciseDocument};
+use crate::search::references::IDENTIFIER_TOKENIZER_NAME;
+use crate::search::symbols::{Symbol, SYMBOL_TOKENIZER_NAME};
+use crate::search::trigram::TRIGRAM_TOKENIZER_NAME;
+
+/// The version of the index layout, persisted next to the index. Bump it whenever fields,
+/// their options or the way values are written change, so that existing indexes are
+/// rebuilt on the next start.
+pub(crate) const SCHEMA_VERSION: u32 = 1;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct CodeFileDto {
+    pub file_id: String,
     pub repo_name: String,
     pub repo_path: String,
     pub repo_type: String,
@@ -14,10 +31,68 @@ pub struct CodeFileDto {
     pub file_size: u64,
     pub file_last_updated: chrono::DateTime<chrono::Utc>,
     pub file_language: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
     pub file_content: String,
+    pub file_hash: String,
+    /// The last commit that changed the file, empty outside of git repos.
+    pub commit_sha: String,
+    pub branch: Vec<String>,
+    pub file_content_policy: FileContentPolicy,
+}
+
+/// How much of a file's content made it into the index.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FileContentPolicy {
+    Full,
+    Truncated,
+    MetadataOnly,
+    NameOnly,
+}
+
+impl FileContentPolicy {
+    pub fn get_name(&self) -> &str {
+        match self {
+            FileContentPolicy::Full => "full",
+            FileContentPolicy::Truncated => "truncated",
+            FileContentPolicy::MetadataOnly => "metadata_only",
+            FileContentPolicy::NameOnly => "name_only",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "full" => Some(FileContentPolicy::Full),
+            "truncated" => Some(FileContentPolicy::Truncated),
+            "metadata_only" => Some(FileContentPolicy::MetadataOnly),
+            "name_only" => Some(FileContentPolicy::NameOnly),
+            _ => None,
+        }
+    }
+}
+
+/// The index holds a document per file, plus line chunk documents for longer files and
+/// the precise code navigation data of files covered by a repo's precise index dump.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DocType {
+    File,
+    Chunk,
+    Precise,
+}
+
+impl DocType {
+    pub fn get_name(&self) -> &str {
+        match self {
+            DocType::File => "file",
+            DocType::Chunk => "chunk",
+            DocType::Precise => "precise",
+        }
+    }
 }
 
+#[derive(Clone, Copy)]
 pub enum CodeSchemaFields {
+    FileId,
     RepoName,
     RepoPath,
     RepoType,
@@ -28,11 +103,31 @@ pub enum CodeSchemaFields {
     FileLastUpdated,
     FileLanguage,
     FileContent,
+    FileHash,
+    CommitSha,
+    Branch,
+    FileContentPolicy,
+    DocType,
+    ParentId,
+    ChunkContent,
+    ChunkStartLine,
+    ChunkEndLine,
+    FileTrigrams,
+    FilePathRaw,
+    FileDirectory,
+    FileSymbols,
+    SymbolNames,
+    Identifiers,
+    PreciseSymbols,
+    PreciseDefinitions,
+    PreciseOccurrences,
+    PreciseHovers,
 }
 
 impl CodeSchemaFields {
     pub fn get_name(&self) -> &str {
         match self {
+            CodeSchemaFields::FileId => "file_id",
             CodeSchemaFields::RepoName => "repo_name",
             CodeSchemaFields::RepoPath => "repo_path",
             CodeSchemaFields::RepoType => "repo_type",
@@ -43,34 +138,105 @@ impl CodeSchemaFields {
             CodeSchemaFields::FileLastUpdated => "file_last_updated",
             CodeSchemaFields::FileLanguage => "file_language",
             CodeSchemaFields::FileContent => "file_content",
+            CodeSchemaFields::FileHash => "file_hash",
+            CodeSchemaFields::CommitSha => "commit_sha",
+            CodeSchemaFields::Branch => "branch",
+            CodeSchemaFields::FileContentPolicy => "file_content_policy",
+            CodeSchemaFields::DocType => "doc_type",
+            CodeSchemaFields::ParentId => "parent_id",
+            CodeSchemaFields::ChunkContent => "chunk_content",
+            CodeSchemaFields::ChunkStartLine => "chunk_start_line",
+            CodeSchemaFields::ChunkEndLine => "chunk_end_line",
+            CodeSchemaFields::FileTrigrams => "file_trigrams",
+            CodeSchemaFields::FilePathRaw => "file_path_raw",
+            CodeSchemaFields::FileDirectory => "file_directory",
+            CodeSchemaFields::FileSymbols => "file_symbols",
+            CodeSchemaFields::SymbolNames => "symbol_names",
+            CodeSchemaFields::Identifiers => "identifiers",
+            CodeSchemaFields::PreciseSymbols => "precise_symbols",
+            CodeSchemaFields::PreciseDefinitions => "precise_definitions",
+            CodeSchemaFields::PreciseOccurrences => "precise_occurrences",
+            CodeSchemaFields::PreciseHovers => "precise_hovers",
         }
     }
 
-    pub fn get_type(&self) -> TextOptions {
+    pub fn get_type(&self) -> SchemaFieldType {
         match self {
-            CodeSchemaFields::RepoName => STRING | STORED,
-            CodeSchemaFields::RepoPath => TEXT | STORED,
-            CodeSchemaFields::RepoType => STRING | STORED,
-            CodeSchemaFields::FileName => TEXT | STORED,
-            CodeSchemaFields::FilePath => TEXT | STORED,
-            CodeSchemaFields::FileExt => STRING | STORED,
-            CodeSchemaFields::FileSize => STORED.into(),
-            CodeSchemaFields::FileLastUpdated => STORED.into(),
-            CodeSchemaFields::FileLanguage => STRING | STORED,
-            CodeSchemaFields::FileContent => TEXT | STORED,
+            CodeSchemaFields::FileId => SchemaFieldType::Text(STRING | STORED),
+            CodeSchemaFields::RepoName => SchemaFieldType::Text(STRING | STORED | FAST),
+            CodeSchemaFields::RepoPath => SchemaFieldType::Text(TEXT | STORED),
+            CodeSchemaFields::RepoType => SchemaFieldType::Text(STRING | STORED),
+            CodeSchemaFields::FileName => SchemaFieldType::Text(TEXT | STORED),
+            CodeSchemaFields::FilePath => SchemaFieldType::Text(TEXT | STORED),
+            CodeSchemaFields::FileExt => SchemaFieldType::Text(STRING | STORED | FAST),
+            CodeSchemaFields::FileSize => SchemaFieldType::U64((INDEXED | STORED | FAST).into()),
+            CodeSchemaFields::FileLastUpdated => SchemaFieldType::Date(
+                DateOptions::from(INDEXED | STORED | FAST).set_precision(DateTimePrecision::Milliseconds),
+            ),
+            CodeSchemaFields::FileLanguage => SchemaFieldType::Text(STRING | STORED | FAST),
+            CodeSchemaFields::FileContent => SchemaFieldType::Text(TEXT | STORED),
+            CodeSchemaFields::FileHash => SchemaFieldType::Bytes(STORED.into()),
+            CodeSchemaFields::CommitSha => SchemaFieldType::Text(STRING | STORED),
+            CodeSchemaFields::Branch => SchemaFieldType::Text(STRING | STORED),
+            CodeSchemaFields::FileContentPolicy => SchemaFieldType::Text(STRING | STORED),
+            CodeSchemaFields::DocType => SchemaFieldType::Text(STRING | STORED),
+            CodeSchemaFields::ParentId => SchemaFieldType::Text(STRING | STORED),
+            CodeSchemaFields::ChunkContent => SchemaFieldType::Text(TEXT),
+            CodeSchemaFields::ChunkStartLine => SchemaFieldType::U64(STORED.into()),
+            CodeSchemaFields::ChunkEndLine => SchemaFieldType::U64(STORED.into()),
+            CodeSchemaFields::FileTrigrams => SchemaFieldType::Text(TextOptions::default().set_indexing_options(
+                TextFieldIndexing::default()
+                    .set_tokenizer(TRIGRAM_TOKENIZER_NAME)
+                    .set_index_option(IndexRecordOption::Basic),
+            )),
+            CodeSchemaFields::FilePathRaw => SchemaFieldType::Text(STRING | STORED | FAST),
+            CodeSchemaFields::FileDirectory => SchemaFieldType::Facet(FacetOptions::default()),
+            CodeSchemaFields::FileSymbols => SchemaFieldType::Text(STORED.into()),
+            CodeSchemaFields::SymbolNames => SchemaFieldType::Text(TextOptions::default().set_indexing_options(
+                TextFieldIndexing::default()
+                    .set_tokenizer(SYMBOL_TOKENIZER_NAME)
+                    .set_index_option(IndexRecordOption::Basic),
+            )),
+            CodeSchemaFields::Identifiers => SchemaFieldType::Text(TextOptions::default().set_indexing_options(
+                TextFieldIndexing::default()
+                    .set_tokenizer(IDENTIFIER_TOKENIZER_NAME)
+                    .set_index_option(IndexRecordOption::WithFreqsAndPositions),
+            )),
+            CodeSchemaFields::PreciseSymbols => SchemaFieldType::Text(STRING),
+            CodeSchemaFields::PreciseDefinitions => SchemaFieldType::Text(STRING),
+            CodeSchemaFields::PreciseOccurrences => SchemaFieldType::Text(STORED.into()),
+            CodeSchemaFields::PreciseHovers => SchemaFieldType::Text(STORED.into()),
         }
     }
 }
 
+/// The options of a field, which decide the kind of values it holds.
+pub enum SchemaFieldType {
+    Text(TextOptions),
+    U64(NumericOptions),
+    Date(DateOptions),
+    Facet(FacetOptions),
+    Bytes(BytesOptions),
+}
+
+fn with_tokenizer(options: TextOptions, tokenizer: FieldTokenizer) -> TextOptions {
+    let indexing = options.get_indexing_options()
+        .cloned()
+        .unwrap_or_default()
+        .set_tokenizer(tokenizer.get_name());
+    options.set_indexing_options(indexing)
+}
+
 pub(crate) struct CodeFileSchema {
     schema: tantivy::schema::Schema,
 }
 
 impl CodeFileSchema {
-    pub fn create() -> tantivy::Result<Self> {
+    pub fn create(tokenizers: &Tokenizers) -> tantivy::Result<Self> {
         let schema = {
             let mut schema_builder = tantivy::schema::Schema::builder();
             let fields = vec![
+                CodeSchemaFields::FileId,
                 CodeSchemaFields::RepoName,
                 CodeSchemaFields::RepoPath,
                 CodeSchemaFields::RepoType,
@@ -81,9 +247,54 @@ impl CodeFileSchema {
                 CodeSchemaFields::FileLastUpdated,
                 CodeSchemaFields::FileLanguage,
                 CodeSchemaFields::FileContent,
+                CodeSchemaFields::FileHash,
+                CodeSchemaFields::CommitSha,
+                CodeSchemaFields::Branch,
+                CodeSchemaFields::FileContentPolicy,
+                CodeSchemaFields::DocType,
+                CodeSchemaFields::ParentId,
+                CodeSchemaFields::ChunkContent,
+                CodeSchemaFields::ChunkStartLine,
+                CodeSchemaFields::ChunkEndLine,
+                CodeSchemaFields::FileTrigrams,
+                CodeSchemaFields::FilePathRaw,
+                CodeSchemaFields::FileDirectory,
+                CodeSchemaFields::FileSymbols,
+                CodeSchemaFields::SymbolNames,
+                CodeSchemaFields::Identifiers,
+                CodeSchemaFields::PreciseSymbols,
+                CodeSchemaFields::PreciseDefinitions,
+                CodeSchemaFields::PreciseOccurrences,
+                CodeSchemaFields::PreciseHovers,
             ];
             for field in fields {
-                schema_builder.add_text_field(field.get_name(), field.get_type());
+                let tokenizer = match field {
+                    CodeSchemaFields::FileContent | CodeSchemaFields::ChunkContent => Some(tokenizers.file_content),
+                    CodeSchemaFields::FileName => Some(tokenizers.file_name),
+                    CodeSchemaFields::FilePath => Some(tokenizers.file_path),
+                    _ => None,
+                };
+                match field.get_type() {
+                    SchemaFieldType::Text(options) => {
+                        let options = match tokenizer {
+                            Some(tokenizer) => with_tokenizer(options, tokenizer),
+                            None => options,
+                        };
+                        schema_builder.add_text_field(field.get_name(), options);
+                    }
+                    SchemaFieldType::U64(options) => {
+                        schema_builder.add_u64_field(field.get_name(), options);
+                    }
+                    SchemaFieldType::Date(options) => {
+                        schema_builder.add_date_field(field.get_name(), options);
+                    }
+                    SchemaFieldType::Facet(options) => {
+                        schema_builder.add_facet_field(field.get_name(), options);
+                    }
+                    SchemaFieldType::Bytes(options) => {
+                        schema_builder.add_bytes_field(field.get_name(), options);
+                    }
+                }
             }
             schema_builder.build()
         };
@@ -101,7 +312,18 @@ impl CodeFileSchema {
         self.schema.get_field(field_name.get_name()).unwrap()
     }
 
-    pub fn create_document(&self, data: CodeFileDto) -> tantivy::Document {
+    pub fn create_file_id(repo_name: &str, file_path: &str) -> String {
+        format!("{}/{}", repo_name, file_path)
+    }
+
+    /// Git files are stored once per distinct content, the blob id tells the versions of a
+    /// path on different branches apart.
+    pub fn create_git_file_id(repo_name: &str, file_path: &str, blob_id: &str) -> String {
+        format!("{}/{}@{}", repo_name, file_path, blob_id)
+    }
+
+    pub fn create_document(&self, data: CodeFileDto, symbols: &[Symbol]) -> tantivy::Document {
+        let id_field = self.get_field(CodeSchemaFields::FileId);
         let repo_name_field = self.get_field(CodeSchemaFields::RepoName);
         let repo_path_field = self.get_field(CodeSchemaFields::RepoPath);
         let repo_type_field = self.get_field(CodeSchemaFields::RepoType);
@@ -112,36 +334,183 @@ impl CodeFileSchema {
         let last_updated_field = self.get_field(CodeSchemaFields::FileLastUpdated);
         let language_field = self.get_field(CodeSchemaFields::FileLanguage);
         let content_field = self.get_field(CodeSchemaFields::FileContent);
-        return doc!(
+        let hash_field = self.get_field(CodeSchemaFields::FileHash);
+        let commit_sha_field = self.get_field(CodeSchemaFields::CommitSha);
+        let branch_field = self.get_field(CodeSchemaFields::Branch);
+        let content_policy_field = self.get_field(CodeSchemaFields::FileContentPolicy);
+        let doc_type_field = self.get_field(CodeSchemaFields::DocType);
+        let parent_id_field = self.get_field(CodeSchemaFields::ParentId);
+        let trigrams_field = self.get_field(CodeSchemaFields::FileTrigrams);
+        let path_raw_field = self.get_field(CodeSchemaFields::FilePathRaw);
+        let directory_field = self.get_field(CodeSchemaFields::FileDirectory);
+        let identifiers_field = self.get_field(CodeSchemaFields::Identifiers);
+        let directory_facet = Self::get_directory_facet(&data.file_path);
+        let mut doc = doc!(
+            doc_type_field => DocType::File.get_name(),
+            parent_id_field => data.file_id.clone(),
+            id_field => data.file_id,
             repo_name_field => data.repo_name,
             repo_path_field => data.repo_path,
             repo_type_field => data.repo_type,
             name_field => data.file_name,
+            path_raw_field => data.file_path.clone(),
             path_field => data.file_path,
             ext_field => data.file_ext,
-            size_field => data.file_size.to_string(),
+            size_field => data.file_size,
             last_updated_field => crate::utils::convert_datetime_chrono_to_tantivy(&data.file_last_updated),
             language_field => data.file_language,
+            trigrams_field => data.file_content.clone(),
+            identifiers_field => data.file_content.clone(),
             content_field => data.file_content,
+            hash_field => Self::hash_to_bytes(&data.file_hash),
+            commit_sha_field => data.commit_sha,
+            content_policy_field => data.file_content_policy.get_name(),
+        );
+        for branch in data.branch {
+            doc.add_text(branch_field, branch);
+        }
+        if let Some(directory) = directory_facet {
+            doc.add_facet(directory_field, directory);
+        }
+        let symbols_field = self.get_field(CodeSchemaFields::FileSymbols);
+        let symbol_names_field = self.get_field(CodeSchemaFields::SymbolNames);
+        for symbol in symbols {
+            doc.add_text(symbols_field, symbol.encode());
+            doc.add_text(symbol_names_field, &symbol.name);
+        }
+        doc
+    }
+
+    /// Chunk documents only carry the chunk text, its line range and the fields search
+    /// filters on. Results are built from the parent file document.
+    pub fn create_chunk_document(&self, data: &CodeFileDto, chunk: &LineChunk, symbols: &[Symbol]) -> tantivy::Document {
+        let id_field = self.get_field(CodeSchemaFields::FileId);
+        let repo_name_field = self.get_field(CodeSchemaFields::RepoName);
+        let ext_field = self.get_field(CodeSchemaFields::FileExt);
+        let language_field = self.get_field(CodeSchemaFields::FileLanguage);
+        let branch_field = self.get_field(CodeSchemaFields::Branch);
+        let doc_type_field = self.get_field(CodeSchemaFields::DocType);
+        let parent_id_field = self.get_field(CodeSchemaFields::ParentId);
+        let chunk_content_field = self.get_field(CodeSchemaFields::ChunkContent);
+        let start_line_field = self.get_field(CodeSchemaFields::ChunkStartLine);
+        let end_line_field = self.get_field(CodeSchemaFields::ChunkEndLine);
+        let path_raw_field = self.get_field(CodeSchemaFields::FilePathRaw);
+        let size_field = self.get_field(CodeSchemaFields::FileSize);
+        let last_updated_field = self.get_field(CodeSchemaFields::FileLastUpdated);
+        let mut doc = doc!(
+            doc_type_field => DocType::Chunk.get_name(),
+            parent_id_field => data.file_id.clone(),
+            id_field => Self::create_chunk_id(&data.file_id, chunk.start_line, chunk.end_line),
+            repo_name_field => data.repo_name.clone(),
+            path_raw_field => data.file_path.clone(),
+            ext_field => data.file_ext.clone(),
+            language_field => data.file_language.clone(),
+            size_field => data.file_size,
+            last_updated_field => crate::utils::convert_datetime_chrono_to_tantivy(&data.file_last_updated),
+            chunk_content_field => chunk.content.clone(),
+            start_line_field => chunk.start_line as u64,
+            end_line_field => chunk.end_line as u64,
+        );
+        for branch in &data.branch {
+            doc.add_text(branch_field, branch);
+        }
+        let symbol_names_field = self.get_field(CodeSchemaFields::SymbolNames);
+        for symbol in symbols.iter().filter(|symbol| (chunk.start_line..=chunk.end_line).contains(&symbol.line)) {
+            doc.add_text(symbol_names_field, &symbol.name);
+        }
+        doc
+    }
+
+    /// Precise documents hold the occurrences and hover texts a precise index dump has for a
+    /// file, indexed by the symbols occurring and defined in it. The hash of the dump they
+    /// were imported from is kept in the file hash.
+    pub fn create_precise_document(&self, repo_name: &str, document: &PreciseDocument, dump_hash: &str) -> tantivy::Document {
+        let doc_type_field = self.get_field(CodeSchemaFields::DocType);
+        let parent_id_field = self.get_field(CodeSchemaFields::ParentId);
+        let repo_name_field = self.get_field(CodeSchemaFields::RepoName);
+        let path_raw_field = self.get_field(CodeSchemaFields::FilePathRaw);
+        let hash_field = self.get_field(CodeSchemaFields::FileHash);
+        let symbols_field = self.get_field(CodeSchemaFields::PreciseSymbols);
+        let definitions_field = self.get_field(CodeSchemaFields::PreciseDefinitions);
+        let occurrences_field = self.get_field(CodeSchemaFields::PreciseOccurrences);
+        let hovers_field = self.get_field(CodeSchemaFields::PreciseHovers);
+        let mut doc = doc!(
+            doc_type_field => DocType::Precise.get_name(),
+            parent_id_field => Self::create_precise_id(repo_name, &document.file_path),
+            repo_name_field => repo_name,
+            path_raw_field => document.file_path.clone(),
+            hash_field => Self::hash_to_bytes(dump_hash),
         );
+        let mut symbols = HashSet::new();
+        let mut definitions = HashSet::new();
+        for occurrence in &document.occurrences {
+            doc.add_text(occurrences_field, occurrence.encode());
+            if symbols.insert(occurrence.symbol.as_str()) {
+                doc.add_text(symbols_field, &occurrence.symbol);
+            }
+            if occurrence.is_definition && definitions.insert(occurrence.symbol.as_str()) {
+                doc.add_text(definitions_field, &occurrence.symbol);
+            }
+        }
+        for hover in &document.hovers {
+            doc.add_text(hovers_field, serde_json::to_string(hover).unwrap());
+        }
+        doc
+    }
+
+    pub fn create_precise_id(repo_name: &str, file_path: &str) -> String {
+        format!("{}/{}#precise", repo_name, file_path)
+    }
+
+    /// The directory of a file as a facet, like `/src/search`. Files at the root of the
+    /// repo have none.
+    pub fn get_directory_facet(file_path: &str) -> Option<Facet> {
+        let (directory, _) = file_path.rsplit_once('/')?;
+        Some(Facet::from_path(directory.split('/')))
+    }
+
+    /// Hashes are hex digests, stored as their raw bytes.
+    fn hash_to_bytes(hash: &str) -> Vec<u8> {
+        crate::utils::decode_hex(hash).unwrap_or_else(|| hash.as_bytes().to_vec())
+    }
+
+    /// The first directory of a path, or `/` for files at the root of the repo.
+    pub fn get_top_level_dir(file_path: &str) -> &str {
+        match file_path.split_once('/') {
+            Some((top_level_dir, _)) => top_level_dir,
+            None => "/",
+        }
+    }
+
+    pub fn create_chunk_id(file_id: &str, start_line: usize, end_line: usize) -> String {
+        format!("{}#L{}-{}", file_id, start_line, end_line)
     }
 
     pub fn create_code_file_dto(&self, doc: &tantivy::Document) -> tantivy::Result<CodeFileDto> {
+        let file_id = self.extract_text_field(doc, CodeSchemaFields::FileId)?;
         let repo_name = self.extract_text_field(doc, CodeSchemaFields::RepoName)?;
         let repo_path = self.extract_text_field(doc, CodeSchemaFields::RepoPath)?;
         let repo_type = self.extract_text_field(doc, CodeSchemaFields::RepoType)?;
         let file_name = self.extract_text_field(doc, CodeSchemaFields::FileName)?;
         let file_path = self.extract_text_field(doc, CodeSchemaFields::FilePath)?;
         let file_ext = self.extract_text_field(doc, CodeSchemaFields::FileExt)?;
-        let file_size = self.extract_text_field(doc, CodeSchemaFields::FileSize)?.parse().unwrap();
+        let file_size = self.extract_u64_field(doc, CodeSchemaFields::FileSize)?;
         let file_language = self.extract_text_field(doc, CodeSchemaFields::FileLanguage)?;
         let file_content = self.extract_text_field(doc, CodeSchemaFields::FileContent)?;
+        let file_hash = self.extract_hash_field(doc)?;
+        let commit_sha = self.extract_text_field(doc, CodeSchemaFields::CommitSha)?;
+        let branch = self.extract_text_values(doc, CodeSchemaFields::Branch);
+        let file_content_policy = self.extract_text_values(doc, CodeSchemaFields::FileContentPolicy)
+            .first()
+            .and_then(|name| FileContentPolicy::from_name(name))
+            .unwrap_or(FileContentPolicy::Full);
 
         let file_last_updated = self.extract_date_field(doc, CodeSchemaFields::FileLastUpdated)?;
         let file_last_updated = chrono::DateTime::from_timestamp_millis(file_last_updated.into_timestamp_millis())
-            .unwrap_or_else(|| chrono::Utc::now());
+            .unwrap_or_else(chrono::Utc::now);
 
         Ok(CodeFileDto {
+            file_id,
             repo_name,
             repo_path,
             repo_type,
@@ -152,14 +521,68 @@ impl CodeFileSchema {
             file_last_updated,
             file_language,
             file_content,
+            file_hash,
+            commit_sha,
+            branch,
+            file_content_policy,
         })
     }
 
-    fn extract_date_field(&self, doc: &tantivy::Document, field_name: CodeSchemaFields) -> tantivy::Result<tantivy::DateTime> {
-        Ok(doc.get_first(self.get_field(field_name)).unwrap().as_date().unwrap().clone())
+    pub(crate) fn extract_symbols(&self, doc: &tantivy::Document) -> Vec<Symbol> {
+        self.extract_text_values(doc, CodeSchemaFields::FileSymbols).iter()
+            .filter_map(|value| Symbol::decode(value))
+            .collect()
+    }
+
+    /// The occurrences and hover texts stored in a precise document.
+    pub(crate) fn extract_precise_document(&self, doc: &tantivy::Document) -> tantivy::Result<PreciseDocument> {
+        Ok(PreciseDocument {
+            file_path: self.extract_text_field(doc, CodeSchemaFields::FilePathRaw)?,
+            occurrences: self.extract_text_values(doc, CodeSchemaFields::PreciseOccurrences).iter()
+                .filter_map(|value| Occurrence::decode(value))
+                .collect(),
+            hovers: self.extract_text_values(doc, CodeSchemaFields::PreciseHovers).iter()
+                .filter_map(|value| serde_json::from_str(value).ok())
+                .collect(),
+        })
+    }
+
+    pub(crate) fn extract_u64_field(&self, doc: &tantivy::Document, field_name: CodeSchemaFields) -> tantivy::Result<u64> {
+        doc.get_first(self.get_field(field_name))
+            .and_then(|value| value.as_u64())
+            .ok_or_else(|| Self::missing_field(field_name, "u64"))
+    }
+
+    pub(crate) fn extract_date_field(&self, doc: &tantivy::Document, field_name: CodeSchemaFields) -> tantivy::Result<tantivy::DateTime> {
+        doc.get_first(self.get_field(field_name))
+            .and_then(|value| value.as_date())
+            .ok_or_else(|| Self::missing_field(field_name, "date"))
+    }
+
+    pub(crate) fn extract_hash_field(&self, doc: &tantivy::Document) -> tantivy::Result<String> {
+        doc.get_first(self.get_field(CodeSchemaFields::FileHash))
+            .and_then(|value| value.as_bytes())
+            .map(crate::utils::encode_hex)
+            .ok_or_else(|| Self::missing_field(CodeSchemaFields::FileHash, "bytes"))
+    }
+
+    /// Documents written with another schema can lack fields or hold other kinds of values,
+    /// which is reported rather than panicking.
+    fn missing_field(field_name: CodeSchemaFields, kind: &str) -> TantivyError {
+        TantivyError::SchemaError(format!("Field {} is missing or does not hold a {} value", field_name.get_name(), kind))
+    }
+
+    pub(crate) fn extract_text_values(&self, doc: &tantivy::Document, field_name: CodeSchemaFields) -> Vec<String> {
+        doc.get_all(self.get_field(field_name))
+            .filter_map(|value| value.as_text())
+            .map(|value| value.to_string())
+            .collect()
     }
 
-    fn extract_text_field(&self, doc: &tantivy::Document, field_name: CodeSchemaFields) -> tantivy::Result<String> {
-        Ok(doc.get_first(self.get_field(field_name)).unwrap().as_text().unwrap().to_string())
+    pub(crate) fn extract_text_field(&self, doc: &tantivy::Document, field_name: CodeSchemaFields) -> tantivy::Result<String> {
+        doc.get_first(self.get_field(field_name))
+            .and_then(|value| value.as_text())
+            .map(|value| value.to_string())
+            .ok_or_else(|| Self::missing_field(field_name, "text"))
     }
 }
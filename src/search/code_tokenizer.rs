@@ -0,0 +1,214 @@
+use tantivy::tokenizer::{LowerCaser, RemoveLongFilter, TextAnalyzer, Token, TokenStream, Tokenizer};
+
+pub(crate) const CODE_TOKENIZER_NAME: &str = "code";
+
+/// Characters that form operator tokens, like `::`, `->` or `!=`. Brackets, quotes and
+/// separators like `,` and `;` are not searchable on their own.
+const OPERATOR_CHARS: &str = "+-*/%=<>!&|^~?:.@#$";
+
+/// Operators that join identifiers into a qualified name, like `std::fs` or `foo.bar`.
+const NAMESPACE_SEPARATORS: [&str; 4] = ["::", ".", "/", "->"];
+
+pub(crate) fn create_code_analyzer() -> TextAnalyzer {
+    TextAnalyzer::builder(CodeTokenizer)
+        .filter(RemoveLongFilter::limit(100))
+        .filter(LowerCaser)
+        .build()
+}
+
+/// Tokenizes source code. Identifiers are emitted whole and split into their camelCase and
+/// snake_case parts, so `parseQuery` is found by `parse`, `query` and `parsequery`.
+/// Qualified names like `std::fs` are also emitted whole, and operators are tokens too.
+#[derive(Clone)]
+pub(crate) struct CodeTokenizer;
+
+pub(crate) struct CodeTokenStream {
+    tokens: Vec<Token>,
+    index: usize,
+}
+
+impl Tokenizer for CodeTokenizer {
+    type TokenStream<'a> = CodeTokenStream;
+
+    fn token_stream<'a>(&'a mut self, text: &'a str) -> Self::TokenStream<'a> {
+        CodeTokenStream {
+            tokens: tokenize(text),
+            index: 0,
+        }
+    }
+}
+
+impl TokenStream for CodeTokenStream {
+    fn advance(&mut self) -> bool {
+        self.index += 1;
+        self.index <= self.tokens.len()
+    }
+
+    fn token(&self) -> &Token {
+        &self.tokens[self.index - 1]
+    }
+
+    fn token_mut(&mut self) -> &mut Token {
+        &mut self.tokens[self.index - 1]
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum SegmentKind {
+    Word,
+    Operator,
+}
+
+struct Segment {
+    kind: SegmentKind,
+    start: usize,
+    end: usize,
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+fn split_segments(text: &str) -> Vec<Segment> {
+    let mut segments: Vec<Segment> = Vec::new();
+    for (start, c) in text.char_indices() {
+        let kind = if is_word_char(c) {
+            SegmentKind::Word
+        } else if OPERATOR_CHARS.contains(c) {
+            SegmentKind::Operator
+        } else {
+            continue;
+        };
+        let end = start + c.len_utf8();
+        match segments.last_mut() {
+            Some(segment) if segment.kind == kind && segment.end == start => segment.end = end,
+            _ => segments.push(Segment { kind, start, end }),
+        }
+    }
+    segments
+}
+
+fn tokenize(text: &str) -> Vec<Token> {
+    let segments = split_segments(text);
+    let mut tokens = Vec::new();
+    let mut position = 0;
+    for (index, segment) in segments.iter().enumerate() {
+        if segment.kind == SegmentKind::Operator {
+            push_token(&mut tokens, text, segment.start, segment.end, position);
+            position += 1;
+            continue;
+        }
+
+        // A qualified name starts at a word that is not itself preceded by a separator
+        let continues_name = index >= 2 && is_namespace_separator(text, &segments[index - 1])
+            && segments[index - 2].kind == SegmentKind::Word
+            && segments[index - 2].end == segments[index - 1].start
+            && segments[index - 1].end == segment.start;
+        if !continues_name {
+            let name_end = qualified_name_end(text, &segments[index..]);
+            if name_end > segment.end {
+                push_token(&mut tokens, text, segment.start, name_end, position);
+            }
+        }
+
+        let parts = split_identifier(&text[segment.start..segment.end]);
+        if parts.len() != 1 || parts[0] != (0, segment.end - segment.start) {
+            push_token(&mut tokens, text, segment.start, segment.end, position);
+        }
+        for (part_start, part_end) in &parts {
+            push_token(&mut tokens, text, segment.start + part_start, segment.start + part_end, position);
+            position += 1;
+        }
+        if parts.is_empty() {
+            position += 1;
+        }
+    }
+    tokens
+}
+
+fn is_namespace_separator(text: &str, segment: &Segment) -> bool {
+    segment.kind == SegmentKind::Operator && NAMESPACE_SEPARATORS.contains(&&text[segment.start..segment.end])
+}
+
+/// The end of the qualified name starting at the first segment, or the end of that segment
+/// if no separator and word follow directly.
+fn qualified_name_end(text: &str, segments: &[Segment]) -> usize {
+    let mut end = segments[0].end;
+    let mut index = 1;
+    while index + 1 < segments.len() {
+        let (separator, word) = (&segments[index], &segments[index + 1]);
+        if separator.start != end || word.start != separator.end
+            || word.kind != SegmentKind::Word || !is_namespace_separator(text, separator) {
+            break;
+        }
+        end = word.end;
+        index += 2;
+    }
+    end
+}
+
+/// Splits an identifier on underscores and case changes, returning byte ranges. Acronyms
+/// stay together, `HTTPServer` becomes `HTTP` and `Server`.
+fn split_identifier(identifier: &str) -> Vec<(usize, usize)> {
+    let mut parts = Vec::new();
+    let chars = identifier.char_indices().collect::<Vec<_>>();
+    let mut part_start: Option<usize> = None;
+    for (index, &(offset, c)) in chars.iter().enumerate() {
+        if c == '_' {
+            if let Some(start) = part_start.take() {
+                parts.push((start, offset));
+            }
+            continue;
+        }
+        if let Some(start) = part_start {
+            let previous = chars[index - 1].1;
+            let next = chars.get(index + 1).map(|&(_, next)| next);
+            let lower_to_upper = c.is_uppercase() && (previous.is_lowercase() || previous.is_ascii_digit());
+            let acronym_end = c.is_uppercase() && previous.is_uppercase() && next.is_some_and(|next| next.is_lowercase());
+            if lower_to_upper || acronym_end {
+                parts.push((start, offset));
+                part_start = Some(offset);
+            }
+        } else {
+            part_start = Some(offset);
+        }
+    }
+    if let Some(start) = part_start {
+        parts.push((start, identifier.len()));
+    }
+    parts
+}
+
+fn push_token(tokens: &mut Vec<Token>, text: &str, start: usize, end: usize, position: usize) {
+    tokens.push(Token {
+        offset_from: start,
+        offset_to: end,
+        position,
+        text: text[start..end].to_string(),
+        position_length: 1,
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn token_texts(text: &str) -> Vec<String> {
+        let mut analyzer = create_code_analyzer();
+        let mut token_stream = analyzer.token_stream(text);
+        let mut texts = Vec::new();
+        while token_stream.advance() {
+            texts.push(token_stream.token().text.clone());
+        }
+        texts
+    }
+
+    #[test]
+    fn splits_identifiers_and_keeps_qualified_names() {
+        assert_eq!(token_texts("parseQuery"), vec!["parsequery", "parse", "query"]);
+        assert_eq!(token_texts("query_parser"), vec!["query_parser", "query", "parser"]);
+        assert_eq!(token_texts("HTTPServer"), vec!["httpserver", "http", "server"]);
+        assert_eq!(token_texts("use std::fs;"), vec!["use", "std::fs", "std", "::", "fs"]);
+        assert_eq!(token_texts("a != b"), vec!["a", "!=", "b"]);
+    }
+}
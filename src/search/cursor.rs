@@ -0,0 +1,91 @@
+use std::cmp::Ordering;
+
+use serde::{Deserialize, Serialize};
+
+use crate::search::search_error::SearchError;
+use crate::search::{ResultItem, SortBy};
+
+/// The value a result is ordered by. Greater keys come first, so paths compare reversed to
+/// be listed alphabetically.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum SortKey {
+    Score(f32),
+    Modified(i64),
+    Size(u64),
+    Path(String),
+}
+
+impl SortKey {
+    pub(crate) fn for_result(sort_by: SortBy, result: &ResultItem) -> Self {
+        match sort_by {
+            SortBy::Score => SortKey::Score(result._score),
+            SortBy::Modified => SortKey::Modified(result.data.file_last_updated.timestamp_millis()),
+            SortBy::Size => SortKey::Size(result.data.file_size),
+            SortBy::Path => SortKey::Path(result.data.file_path.clone()),
+        }
+    }
+
+    pub(crate) fn sort_by(&self) -> SortBy {
+        match self {
+            SortKey::Score(_) => SortBy::Score,
+            SortKey::Modified(_) => SortBy::Modified,
+            SortKey::Size(_) => SortBy::Size,
+            SortKey::Path(_) => SortBy::Path,
+        }
+    }
+}
+
+impl PartialOrd for SortKey {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        match (self, other) {
+            (SortKey::Score(a), SortKey::Score(b)) => Some(a.total_cmp(b)),
+            (SortKey::Modified(a), SortKey::Modified(b)) => Some(a.cmp(b)),
+            (SortKey::Size(a), SortKey::Size(b)) => Some(a.cmp(b)),
+            (SortKey::Path(a), SortKey::Path(b)) => Some(b.cmp(a)),
+            _ => None,
+        }
+    }
+}
+
+/// Where a page of results ended. Results are ordered by their sort key, then file id, so the
+/// next page starts right after this key even if files were added or removed in between.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct Cursor {
+    pub(crate) key: SortKey,
+    pub(crate) file_id: String,
+    /// How many results came before the next page, used to size the next fetch.
+    pub(crate) position: usize,
+    /// The time recency was ranked against, in milliseconds since the epoch. Later pages
+    /// rank against it too, so scores don't drift between pages.
+    pub(crate) now: i64,
+}
+
+impl Cursor {
+    /// Whether a result with this key and file id comes after the cursor.
+    pub(crate) fn is_before(&self, key: &SortKey, file_id: &str) -> bool {
+        match key.partial_cmp(&self.key) {
+            Some(Ordering::Less) => true,
+            Some(Ordering::Equal) => file_id > self.file_id.as_str(),
+            _ => false,
+        }
+    }
+
+    /// Encodes the cursor as an opaque, URL safe token.
+    pub(crate) fn encode(&self) -> String {
+        crate::utils::encode_hex(serde_json::to_string(self).unwrap_or_default().as_bytes())
+    }
+
+    /// Decodes a token, which has to come from a search with the same sort order.
+    pub(crate) fn decode(token: &str, sort_by: SortBy) -> Result<Self, SearchError> {
+        let invalid = || SearchError::invalid_query(format!("Invalid cursor: {}", token));
+        let bytes = crate::utils::decode_hex(token).ok_or_else(invalid)?;
+        let cursor: Cursor = serde_json::from_slice(&bytes).map_err(|_| invalid())?;
+        if cursor.key.sort_by() != sort_by {
+            return Err(SearchError::invalid_query(format!(
+                "Cursor was created for sorting by {:?}, not {:?}", cursor.key.sort_by(), sort_by
+            )));
+        }
+        Ok(cursor)
+    }
+}
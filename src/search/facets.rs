@@ -0,0 +1,147 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use tantivy::aggregation::agg_req::{Aggregation, AggregationVariants, Aggregations};
+use tantivy::aggregation::agg_result::{AggregationResult, AggregationResults, BucketResult};
+use tantivy::aggregation::bucket::TermsAggregation;
+use tantivy::aggregation::Key;
+use tantivy::collector::{FacetCollector, FacetCounts};
+use tantivy::schema::Facet;
+
+use crate::search::code_schema::{CodeFileSchema, CodeSchemaFields};
+use crate::search::ResultItem;
+
+/// How many values are counted per facet at most, the most frequent first.
+const MAX_FACET_VALUES: usize = 20;
+
+/// The number of matching files per repository, language, extension and top-level directory,
+/// over all results rather than only the returned page.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Facets {
+    pub repo_name: Vec<FacetCount>,
+    pub file_language: Vec<FacetCount>,
+    pub file_ext: Vec<FacetCount>,
+    pub top_level_dir: Vec<FacetCount>,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct FacetCount {
+    pub value: String,
+    pub count: u64,
+}
+
+const FACET_FIELDS: [CodeSchemaFields; 3] = [
+    CodeSchemaFields::RepoName,
+    CodeSchemaFields::FileLanguage,
+    CodeSchemaFields::FileExt,
+];
+
+/// Counts the directories right below the root of the directory facet, the top-level
+/// directories of the files.
+pub(crate) fn create_directory_collector() -> FacetCollector {
+    let mut collector = FacetCollector::for_field(CodeSchemaFields::FileDirectory.get_name());
+    collector.add_facet(Facet::root());
+    collector
+}
+
+/// A terms aggregation per facet field, named after the field.
+pub(crate) fn create_facet_aggregations() -> Aggregations {
+    FACET_FIELDS.iter()
+        .map(|field| {
+            let terms = TermsAggregation {
+                field: field.get_name().to_string(),
+                size: Some(MAX_FACET_VALUES as u32),
+                ..Default::default()
+            };
+            let aggregation = Aggregation {
+                agg: AggregationVariants::Terms(terms),
+                sub_aggregation: Default::default(),
+            };
+            (field.get_name().to_string(), aggregation)
+        })
+        .collect()
+}
+
+impl Facets {
+    /// The facets of `total` matching files, from the aggregations of the facet fields and
+    /// the directory counts. Files at the root have no directory and are counted as `/`.
+    pub(crate) fn from_index(results: &AggregationResults, directories: &FacetCounts, total: u64) -> Self {
+        let counts = |field: CodeSchemaFields| -> Vec<FacetCount> {
+            let Some(AggregationResult::BucketResult(BucketResult::Terms { buckets, .. })) = results.0.get(field.get_name()) else {
+                return vec![];
+            };
+            buckets.iter()
+                .map(|bucket| FacetCount {
+                    value: match &bucket.key {
+                        Key::Str(value) => value.clone(),
+                        Key::F64(value) => value.to_string(),
+                    },
+                    count: bucket.doc_count,
+                })
+                .collect()
+        };
+        Facets {
+            repo_name: counts(CodeSchemaFields::RepoName),
+            file_language: counts(CodeSchemaFields::FileLanguage),
+            file_ext: counts(CodeSchemaFields::FileExt),
+            top_level_dir: directory_counts(directories, total),
+        }
+    }
+
+    /// Counts the facets of results that were matched outside the index.
+    pub(crate) fn from_results(results: &[ResultItem]) -> Self {
+        Facets {
+            repo_name: count_values(results.iter().map(|result| result.data.repo_name.as_str())),
+            file_language: count_values(results.iter().map(|result| result.data.file_language.as_str())),
+            file_ext: count_values(results.iter().map(|result| result.data.file_ext.as_str())),
+            top_level_dir: count_values(results.iter().map(|result| CodeFileSchema::get_top_level_dir(&result.data.file_path))),
+        }
+    }
+}
+
+fn directory_counts(directories: &FacetCounts, total: u64) -> Vec<FacetCount> {
+    let mut counts = directories.get(Facet::root())
+        .map(|(facet, count)| FacetCount {
+            value: facet.to_path().join("/"),
+            count,
+        })
+        .collect::<Vec<_>>();
+    let root_count = total.saturating_sub(counts.iter().map(|count| count.count).sum());
+    if root_count > 0 {
+        counts.push(FacetCount { value: "/".to_string(), count: root_count });
+    }
+    sort_counts(&mut counts);
+    counts
+}
+
+/// Counts values the way the terms aggregation does, most frequent first and ties by value.
+fn count_values<'a>(values: impl Iterator<Item = &'a str>) -> Vec<FacetCount> {
+    let mut counts: HashMap<&str, u64> = HashMap::new();
+    for value in values {
+        *counts.entry(value).or_default() += 1;
+    }
+    let mut counts = counts.into_iter()
+        .map(|(value, count)| FacetCount { value: value.to_string(), count })
+        .collect::<Vec<_>>();
+    sort_counts(&mut counts);
+    counts
+}
+
+fn sort_counts(counts: &mut Vec<FacetCount>) {
+    counts.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.value.cmp(&b.value)));
+    counts.truncate(MAX_FACET_VALUES);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_most_frequent_values_first() {
+        assert_eq!(count_values(["rs", "ts", "rs", "go", "ts", "rs"].into_iter()), vec![
+            FacetCount { value: "rs".to_string(), count: 3 },
+            FacetCount { value: "ts".to_string(), count: 2 },
+            FacetCount { value: "go".to_string(), count: 1 },
+        ]);
+    }
+}
@@ -0,0 +1,172 @@
+use std::fmt;
+
+use chardetng::EncodingDetector;
+use encoding_rs::{DecoderResult, Encoding};
+use serde::{Serialize, Serializer};
+
+use crate::config::LargeFilePolicy;
+use crate::search::code_schema::FileContentPolicy;
+
+/// How many leading bytes are inspected for NUL bytes when deciding if a file is binary.
+const BINARY_SNIFF_LEN: usize = 8_000;
+
+/// How much of a file gets read for indexing.
+pub(crate) enum ReadPlan {
+    Full,
+    Prefix(u64),
+    Nothing,
+}
+
+impl ReadPlan {
+    /// Decides how to read a file of `file_size` bytes under the size limit of its repo.
+    pub(crate) fn for_file_size(file_size: u64, size_limit: (Option<u64>, LargeFilePolicy)) -> Result<Self, SkipReason> {
+        match size_limit {
+            (Some(max_file_size), policy) if file_size > max_file_size => match policy {
+                LargeFilePolicy::Skip => Err(SkipReason::TooLarge(file_size)),
+                LargeFilePolicy::Truncate => Ok(ReadPlan::Prefix(max_file_size)),
+                LargeFilePolicy::MetadataOnly => Ok(ReadPlan::Nothing),
+            },
+            _ => Ok(ReadPlan::Full),
+        }
+    }
+
+    pub(crate) fn content_policy(&self) -> FileContentPolicy {
+        match self {
+            ReadPlan::Full => FileContentPolicy::Full,
+            ReadPlan::Prefix(_) => FileContentPolicy::Truncated,
+            ReadPlan::Nothing => FileContentPolicy::MetadataOnly,
+        }
+    }
+}
+
+pub(crate) enum DecodedContent {
+    Text(String),
+    Binary,
+}
+
+/// Why a file was left out of the index, reported at the end of an indexing run.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SkipReason {
+    Binary,
+    TooLarge(u64),
+    Unreadable(String),
+}
+
+impl fmt::Display for SkipReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SkipReason::Binary => write!(f, "binary file"),
+            SkipReason::TooLarge(file_size) => write!(f, "too large ({} bytes)", file_size),
+            SkipReason::Unreadable(err) => write!(f, "unreadable: {}", err),
+        }
+    }
+}
+
+impl Serialize for SkipReason {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl From<std::io::Error> for SkipReason {
+    fn from(err: std::io::Error) -> Self {
+        SkipReason::Unreadable(err.to_string())
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SkippedFile {
+    pub file_id: String,
+    pub reason: SkipReason,
+}
+
+/// Decodes file content to text. UTF-8 is used as is, files with a byte order mark are
+/// decoded accordingly, files with NUL bytes are considered binary, and anything else is
+/// transcoded from the detected legacy encoding, replacing bytes that do not decode.
+pub(crate) fn decode_content(bytes: &[u8]) -> DecodedContent {
+    // Binary files can start with bytes that look like a byte order mark, so it only counts
+    // when the rest decodes cleanly to text without NUL characters
+    if let Some((encoding, bom_length)) = Encoding::for_bom(bytes) {
+        return match decode_strictly(encoding, &bytes[bom_length..]) {
+            Some(text) if !text.contains('\0') => DecodedContent::Text(text),
+            _ => DecodedContent::Binary,
+        };
+    }
+
+    let sniff_len = bytes.len().min(BINARY_SNIFF_LEN);
+    if bytes[..sniff_len].contains(&0) {
+        return DecodedContent::Binary;
+    }
+
+    match std::str::from_utf8(bytes) {
+        Ok(text) => return DecodedContent::Text(text.to_string()),
+        // Only the last character is incomplete, as happens when a file is truncated
+        Err(err) if err.error_len().is_none() => {
+            let text = std::str::from_utf8(&bytes[..err.valid_up_to()]).unwrap();
+            return DecodedContent::Text(text.to_string());
+        }
+        Err(_) => {}
+    }
+
+    let mut detector = EncodingDetector::new();
+    detector.feed(bytes, true);
+    let encoding = detector.guess(None, false);
+    log::trace!("Decoding content as {}", encoding.name());
+    let (text, _, _) = encoding.decode(bytes);
+    DecodedContent::Text(text.into_owned())
+}
+
+/// Decodes the bytes without replacing malformed sequences, None if there are any. A
+/// character cut off at the end, as happens when a file is truncated, is dropped.
+fn decode_strictly(encoding: &'static Encoding, bytes: &[u8]) -> Option<String> {
+    let mut decoder = encoding.new_decoder_without_bom_handling();
+    let mut text = String::with_capacity(decoder.max_utf8_buffer_length_without_replacement(bytes.len())?);
+    let (result, _) = decoder.decode_to_string_without_replacement(bytes, &mut text, false);
+    match result {
+        DecoderResult::InputEmpty => Some(text),
+        DecoderResult::OutputFull | DecoderResult::Malformed(..) => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode_text(bytes: &[u8]) -> String {
+        match decode_content(bytes) {
+            DecodedContent::Text(text) => text,
+            DecodedContent::Binary => panic!("decoded as binary"),
+        }
+    }
+
+    #[test]
+    fn nul_bytes_mark_binary_files() {
+        assert!(matches!(decode_content(b"\x7fELF\x02\x01\x01\x00\x00"), DecodedContent::Binary));
+        let mut late_nul = vec![b'a'; BINARY_SNIFF_LEN];
+        late_nul.push(0);
+        assert!(matches!(decode_content(&late_nul), DecodedContent::Text(_)));
+    }
+
+    #[test]
+    fn byte_order_marks_select_the_encoding() {
+        let utf16 = [0xFF, 0xFE, b'h', 0, b'i', 0, 0xE9, 0];
+        assert_eq!(decode_text(&utf16), "hié");
+        assert_eq!(decode_text(b"\xEF\xBB\xBFfn main() {}"), "fn main() {}");
+        assert_eq!(decode_text(&utf16[..7]), "hi");
+    }
+
+    #[test]
+    fn binary_files_starting_like_a_byte_order_mark_stay_binary() {
+        // An unpaired surrogate, and a NUL character
+        assert!(matches!(decode_content(&[0xFF, 0xFE, 0x00, 0xD8, b'a', 0]), DecodedContent::Binary));
+        assert!(matches!(decode_content(&[0xFE, 0xFF, 0, b'a', 0, 0, 0x12, 0x34]), DecodedContent::Binary));
+        assert!(matches!(decode_content(b"\xEF\xBB\xBF\x00\x01\x02"), DecodedContent::Binary));
+    }
+
+    #[test]
+    fn legacy_encodings_are_transcoded() {
+        assert_eq!(decode_text(b"// caf\xE9 cr\xE8me br\xFBl\xE9e\nfn main() {}"), "// café crème brûlée\nfn main() {}");
+        // A prefix read can end in the middle of a character
+        assert_eq!(decode_text("// café".as_bytes().split_last().unwrap().1), "// caf");
+    }
+}
@@ -1,22 +1,213 @@
+use std::collections::{BTreeSet, HashMap, HashSet};
 use std::fs;
-use std::path::PathBuf;
-use std::sync::{Arc, RwLock};
+use std::io::Read;
+use std::ops::Bound;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+use std::sync::atomic::{AtomicUsize, Ordering};
 
 use async_trait::async_trait;
-use log;
+use git2::Oid;
 use rayon::prelude::*;
-use walkdir::{DirEntry, WalkDir};
+use sha2::{Digest, Sha256};
+use tantivy::aggregation::AggregationCollector;
+use tantivy::collector::{Collector, DocSetCollector, TopDocs};
+use tantivy::directory::Directory;
+use tantivy::query::{BooleanQuery, ConstScoreQuery, Occur, Query, RangeQuery, RegexQuery, TermQuery};
+use tantivy::schema::{Field, IndexRecordOption};
+use tantivy::postings::Postings;
+use tantivy::{DocAddress, DocId, DocSet, IndexWriter, SegmentReader, Term, TERMINATED};
 
 use crate::config;
-use crate::config::Config;
-use crate::search::{code_schema, ResultItem, SearchEngine, SearchOptions};
-use crate::search::code_schema::CodeFileSchema;
+use crate::config::{BinaryFilePolicy, Config, PreciseIndexFormat};
+use crate::search::{chunker, code_intel, code_schema, code_tokenizer, facets, file_content, git_repo, languages, repo_walker, query_language, references, regex_query, snippet, symbols, trigram, Facets, CodeIntel, CodeIntelOptions, Location, MatchedChunk, ReferenceOptions, ReferenceTarget, References, ResultItem, SearchEngine, SearchMode, SearchOptions, SearchResults, SortBy};
+use crate::search::code_intel::PreciseDocument;
+use crate::search::code_schema::{CodeFileDto, CodeFileSchema, DocType, FileContentPolicy};
+use crate::search::file_content::{DecodedContent, ReadPlan, SkipReason, SkippedFile};
+use crate::search::git_repo::LastCommits;
+use crate::search::repo_walker::RepoFileFilter;
 use crate::search::search_error::SearchError;
+use crate::search::cursor::{Cursor, SortKey};
+use crate::search::query_language::{Comparison, Filter, FilterKind, FilterValue, ParsedQuery};
+use crate::search::snippet::SnippetGenerator;
+use crate::search::ranking::{Ranker, ScoreExplanation};
+use crate::search::references::{FileReferences, Reference};
+use crate::search::trigram::TrigramQuery;
+
+struct IndexedFileState {
+    file_last_updated: i64,
+    file_hash: String,
+    branch: Vec<String>,
+}
+
+/// A distinct version of a file in a git repo, with the branches it appears on.
+struct GitIndexedFile {
+    commit_sha: String,
+    commit_time: chrono::DateTime<chrono::Utc>,
+    branch: Vec<String>,
+}
+
+impl GitIndexedFile {
+    /// Whether the file has to be (re-)indexed, a version indexed before only changes when
+    /// it is added to or removed from branches.
+    fn needs_indexing(&self, indexed_file: Option<&IndexedFileState>) -> bool {
+        indexed_file.is_none_or(|indexed_file| indexed_file.branch != self.branch)
+    }
+}
+
+/// State shared by the workers of a single `adding_repo_files_to_index` run.
+struct IndexingRun {
+    parent_id_field: Field,
+    index_writer: RwLock<IndexWriter>,
+    indexed_files: HashMap<String, IndexedFileState>,
+    seen_file_ids: Mutex<HashSet<String>>,
+    added_count: AtomicUsize,
+    updated_count: AtomicUsize,
+    skipped_files: Mutex<Vec<SkippedFile>>,
+}
+
+impl IndexingRun {
+    /// Records that the file still exists and returns its state from the previous run, if any.
+    fn mark_seen(&self, file_id: &str) -> Option<&IndexedFileState> {
+        self.seen_file_ids.lock().unwrap().insert(file_id.to_string());
+        self.indexed_files.get(file_id)
+    }
+
+    /// Adds the file document and its chunk documents, replacing the ones of an earlier run.
+    fn write_documents(&self, file_id: &str, docs: Vec<tantivy::Document>) {
+        let index_writer = self.index_writer.read().unwrap();
+        if self.indexed_files.contains_key(file_id) {
+            index_writer.delete_term(Term::from_field_text(self.parent_id_field, file_id));
+            self.updated_count.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.added_count.fetch_add(1, Ordering::Relaxed);
+        }
+        for doc in docs {
+            if let Err(err) = index_writer.add_document(doc) {
+                log::error!("Failed to add {} to the index: {}", file_id, err);
+            }
+        }
+    }
+
+    /// Records why a file was left out, and drops what an earlier run indexed for it.
+    fn skip_file(&self, file_id: &str, reason: SkipReason) {
+        if self.indexed_files.contains_key(file_id) {
+            let index_writer = self.index_writer.read().unwrap();
+            index_writer.delete_term(Term::from_field_text(self.parent_id_field, file_id));
+        }
+        self.skipped_files.lock().unwrap().push(skipped_file(file_id, reason));
+    }
+
+    /// Removes the files that were not seen during the run and commits. Returns the files
+    /// the run left out.
+    fn finish(self, repo: &config::Repo) -> Result<Vec<SkippedFile>, SearchError> {
+        let seen_file_ids = self.seen_file_ids.into_inner().unwrap();
+        let mut deleted_count = 0;
+        let mut index_writer = self.index_writer.into_inner().unwrap();
+        for file_id in self.indexed_files.keys().filter(|file_id| !seen_file_ids.contains(*file_id)) {
+            log::trace!("Removing deleted file from index: {}", file_id);
+            index_writer.delete_term(Term::from_field_text(self.parent_id_field, file_id));
+            deleted_count += 1;
+        }
+        index_writer.commit()?;
+
+        let added_count = self.added_count.into_inner();
+        let updated_count = self.updated_count.into_inner();
+        let skipped_files = self.skipped_files.into_inner().unwrap();
+        log::info!(
+            "Indexed repo {}: {} added, {} updated, {} deleted, {} skipped, {} unchanged",
+            repo.name,
+            added_count,
+            updated_count,
+            deleted_count,
+            skipped_files.len(),
+            seen_file_ids.len() - added_count - updated_count - skipped_files.len(),
+        );
+        log_skipped_files(&repo.name, &skipped_files);
+        Ok(skipped_files)
+    }
+}
+
+/// Hashes the content of a file. Files that were not read in full also hash their size, so
+/// growing a file past the size limit is still noticed as a change.
+fn create_file_hash(file_content: &[u8], file_size: u64, read_plan: &ReadPlan) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(file_content);
+    if !matches!(read_plan, ReadPlan::Full) {
+        hasher.update(file_size.to_le_bytes());
+    }
+    format!("{:x}", hasher.finalize())
+}
+
+fn skipped_file(file_id: &str, reason: SkipReason) -> SkippedFile {
+    log::debug!("Skipping file {}: {}", file_id, reason);
+    SkippedFile {
+        file_id: file_id.to_string(),
+        reason,
+    }
+}
+
+/// Summarizes the files an indexing run left out, binary files are only listed at debug level.
+fn log_skipped_files(repo_name: &str, skipped_files: &[SkippedFile]) {
+    let binary_count = skipped_files.iter()
+        .filter(|skipped_file| matches!(skipped_file.reason, SkipReason::Binary))
+        .count();
+    if binary_count > 0 {
+        log::info!("Skipped {} binary files in repo {}", binary_count, repo_name);
+    }
+    for skipped_file in skipped_files {
+        if !matches!(skipped_file.reason, SkipReason::Binary) {
+            log::warn!("Skipped {}: {}", skipped_file.file_id, skipped_file.reason);
+        }
+    }
+}
+
+/// The file in the index directory holding the schema version the index was built with.
+const SCHEMA_VERSION_FILE: &str = "schema_version";
+
+/// Opens the index in the directory, or creates it. An index built with another schema
+/// version, or whose fields differ from the schema, like after changing the tokenizers, is
+/// replaced by an empty one, which `initialize` then fills. Returns whether that happened.
+fn open_index(directory: tantivy::directory::MmapDirectory, schema: tantivy::schema::Schema) -> tantivy::Result<(tantivy::Index, bool)> {
+    let version_path = Path::new(SCHEMA_VERSION_FILE);
+    let rebuilt = if tantivy::Index::exists(&directory)? {
+        let index_version = directory.atomic_read(version_path).ok()
+            .and_then(|version| String::from_utf8_lossy(&version).trim().parse::<u32>().ok());
+        match (index_version, tantivy::Index::open(directory.clone())) {
+            (Some(code_schema::SCHEMA_VERSION), Ok(index)) if index.schema() == schema => return Ok((index, false)),
+            (Some(code_schema::SCHEMA_VERSION), Ok(_)) => {
+                log::warn!("Index fields differ from the configured schema, rebuilding the index");
+            }
+            (Some(code_schema::SCHEMA_VERSION), Err(err)) => {
+                log::warn!("Index could not be opened, rebuilding it: {}", err);
+            }
+            (index_version, _) => {
+                let index_version = index_version.map_or("none".to_string(), |version| version.to_string());
+                log::warn!("Index has schema version {}, rebuilding it for version {}", index_version, code_schema::SCHEMA_VERSION);
+            }
+        }
+        true
+    } else {
+        false
+    };
+    // Creating an index over an existing one starts it empty, the old segments are
+    // garbage collected on the first commit
+    let index = tantivy::Index::create(directory.clone(), schema, tantivy::IndexSettings::default())?;
+    directory.atomic_write(version_path, code_schema::SCHEMA_VERSION.to_string().as_bytes())?;
+    Ok((index, rebuilt))
+}
 
 pub struct FileSearchEngine {
     config: Config,
     schema: CodeFileSchema,
     index: tantivy::Index,
+    ranker: Arc<Ranker>,
+    index_rebuilt: bool,
+    /// The files the last indexing run of each repo left out.
+    skipped_files: RwLock<HashMap<String, Vec<SkippedFile>>>,
+    /// The last commits of git files looked up by earlier runs, by repo and by the commit
+    /// of a ref they were looked up from.
+    last_commits: Mutex<HashMap<String, HashMap<String, LastCommits>>>,
 }
 
 impl FileSearchEngine {
@@ -38,134 +229,2019 @@ impl FileSearchEngine {
         };
 
         log::info!("Opening index");
-        let code_file_schema = CodeFileSchema::create().unwrap();
-        let index = tantivy::Index::open_or_create(index_path, code_file_schema.get_schema().clone())?;
+        let code_file_schema = CodeFileSchema::create(&config.indexer.tokenizers).unwrap();
+        let (index, index_rebuilt) = open_index(index_path, code_file_schema.get_schema().clone())?;
+        index.tokenizers().register(code_tokenizer::CODE_TOKENIZER_NAME, code_tokenizer::create_code_analyzer());
+        index.tokenizers().register(trigram::TRIGRAM_TOKENIZER_NAME, trigram::create_trigram_analyzer());
+        index.tokenizers().register(symbols::SYMBOL_TOKENIZER_NAME, symbols::create_symbol_analyzer());
+        index.tokenizers().register(references::IDENTIFIER_TOKENIZER_NAME, references::create_identifier_analyzer());
         log::info!("Index opened successfully");
+        let ranker = Ranker::new(&config.ranking)
+            .map_err(|err| tantivy::TantivyError::InvalidArgument(format!("Invalid ranking config: {}", err)))?;
 
         Ok(Self {
             index,
+            index_rebuilt,
+            skipped_files: RwLock::new(HashMap::new()),
+            last_commits: Mutex::new(HashMap::new()),
+            ranker: Arc::new(ranker),
             schema: code_file_schema,
             config: config.clone(),
         })
     }
 
-    fn filter_skip_patterns(&self, entry: &DirEntry, repo: &config::Repo) -> bool {
-        let path = entry.path();
-        let path_str = path.to_str().unwrap();
-        repo.skip_patterns.iter().any(|pattern| {
-            path_str.contains(pattern)
+    pub(crate) fn get_config(&self) -> &Config {
+        &self.config
+    }
+
+    /// The files the last indexing run of the repo left out, with the reason.
+    pub(crate) fn get_skipped_files(&self, repo_name: &str) -> Vec<SkippedFile> {
+        self.skipped_files.read().unwrap().get(repo_name).cloned().unwrap_or_default()
+    }
+
+    fn read_repo_file(&self, repo: &config::Repo, path: &Path, file_id: String, file_path: String) -> Result<CodeFileDto, SkipReason> {
+        let metadata = fs::metadata(path)?;
+        let file_size = metadata.len();
+        let read_plan = ReadPlan::for_file_size(file_size, repo.file_size_limit(&self.config.indexer))?;
+        let file_content = match read_plan {
+            ReadPlan::Full => fs::read(path)?,
+            ReadPlan::Prefix(max_file_size) => {
+                let mut file_content = Vec::new();
+                fs::File::open(path)?.take(max_file_size).read_to_end(&mut file_content)?;
+                file_content
+            }
+            ReadPlan::Nothing => Vec::new(),
+        };
+        let file_hash = create_file_hash(&file_content, file_size, &read_plan);
+        self.create_file_dto(repo, file_id, file_path, file_content, file_size, &read_plan, metadata.modified()?.into(), file_hash)
+    }
+
+    /// Builds the document data of a file from its raw content. Binary files are skipped or
+    /// indexed by name only, depending on the configured policy.
+    #[allow(clippy::too_many_arguments)]
+    fn create_file_dto(
+        &self,
+        repo: &config::Repo,
+        file_id: String,
+        file_path: String,
+        file_content: Vec<u8>,
+        file_size: u64,
+        read_plan: &ReadPlan,
+        file_last_updated: chrono::DateTime<chrono::Utc>,
+        file_hash: String,
+    ) -> Result<CodeFileDto, SkipReason> {
+        let mut file_content_policy = read_plan.content_policy();
+        let file_content = match file_content::decode_content(&file_content) {
+            DecodedContent::Text(file_content) => file_content,
+            DecodedContent::Binary => match self.config.indexer.binary_files {
+                BinaryFilePolicy::Skip => return Err(SkipReason::Binary),
+                BinaryFilePolicy::NameOnly => {
+                    file_content_policy = FileContentPolicy::NameOnly;
+                    "".to_string()
+                }
+            },
+        };
+
+        let path = Path::new(&file_path);
+        let file_name = match path.file_name() {
+            Some(file_name) => file_name.to_string_lossy().to_string(),
+            None => file_path.clone(),
+        };
+        let file_ext = match path.extension() {
+            Some(ext) => ext.to_string_lossy().to_string(),
+            None => "".to_string(),
+        };
+        let file_language = languages::detect_language(&file_path, &file_content).to_string();
+
+        Ok(CodeFileDto {
+            file_id,
+            repo_name: repo.name.clone(),
+            repo_path: repo.path.clone(),
+            repo_type: repo.type_.clone(),
+            file_name,
+            file_path,
+            file_ext,
+            file_size,
+            file_last_updated,
+            file_language,
+            file_content,
+            file_hash,
+            commit_sha: "".to_string(),
+            branch: vec![],
+            file_content_policy,
         })
     }
 
     pub(crate) async fn initialize(&self) -> Result<(), SearchError> {
         log::info!("Initializing index for FileSearchEngine");
 
-        if self.config.indexer.force_reindex {
+        self.remove_unconfigured_repos()?;
+
+        if self.config.indexer.force_reindex || self.config.indexer.incremental_reindex || self.index_rebuilt {
+            let full_reindex = self.config.indexer.force_reindex;
             let config = self.config.clone();
             for repo in &config.repos {
-                log::info!("Start indexing repo: {}", repo.name);
-                self.adding_repo_files_to_index(repo)?;
+                log::info!("Start indexing repo: {} (full: {})", repo.name, full_reindex);
+                self.adding_repo_files_to_index(repo, full_reindex)?;
                 log::info!("Finished indexing repo: {}", repo.name);
             }
         } else {
-            log::info!("Skipping indexing because force_reindex is false");
+            log::info!("Skipping indexing because force_reindex and incremental_reindex are false");
         }
 
         Ok(())
     }
 
-    fn adding_repo_files_to_index(&self, repo: &config::Repo) -> Result<(), SearchError> {
-        let index = &self.index;
-        let index_writer_arc = Arc::new(RwLock::new(index.writer(50_000_000)?));
+    /// Removes the documents of repos that are still in the index but no longer in the config.
+    fn remove_unconfigured_repos(&self) -> Result<(), SearchError> {
+        let searcher = self.index.reader()?.searcher();
+        let repo_name_field = self.schema.get_field(code_schema::CodeSchemaFields::RepoName);
+
+        let mut indexed_repo_names = HashSet::new();
+        for segment_reader in searcher.segment_readers() {
+            let inverted_index = segment_reader.inverted_index(repo_name_field)?;
+            let mut terms = inverted_index.terms().stream()?;
+            while terms.advance() {
+                indexed_repo_names.insert(String::from_utf8_lossy(terms.key()).to_string());
+            }
+        }
+
+        let removed_repo_names = indexed_repo_names.iter()
+            .filter(|repo_name| !self.config.repos.iter().any(|repo| &repo.name == *repo_name))
+            .collect::<Vec<_>>();
+        if removed_repo_names.is_empty() {
+            return Ok(());
+        }
+
+        let mut index_writer = self.index.writer(50_000_000)?;
+        for repo_name in removed_repo_names {
+            log::info!("Removing repo that is no longer configured: {}", repo_name);
+            index_writer.delete_term(Term::from_field_text(repo_name_field, repo_name));
+        }
+        index_writer.commit()?;
+        Ok(())
+    }
+
+    fn load_indexed_files(&self, repo: &config::Repo) -> Result<HashMap<String, IndexedFileState>, SearchError> {
+        let searcher = self.index.reader()?.searcher();
+        let repo_name_field = self.schema.get_field(code_schema::CodeSchemaFields::RepoName);
+        let query = BooleanQuery::new(vec![
+            (Occur::Must, Box::new(TermQuery::new(Term::from_field_text(repo_name_field, &repo.name), IndexRecordOption::Basic))),
+            (Occur::Must, self.doc_type_query(DocType::File)),
+        ]);
+        let doc_addresses = searcher.search(&query, &DocSetCollector)?;
+
+        let mut indexed_files = HashMap::with_capacity(doc_addresses.len());
+        for doc_address in doc_addresses {
+            let doc = searcher.doc(doc_address)?;
+            let file_id = self.schema.extract_text_field(&doc, code_schema::CodeSchemaFields::FileId)?;
+            let file_hash = self.schema.extract_hash_field(&doc)?;
+            let file_last_updated = self.schema.extract_date_field(&doc, code_schema::CodeSchemaFields::FileLastUpdated)?;
+            let branch = self.schema.extract_text_values(&doc, code_schema::CodeSchemaFields::Branch);
+            indexed_files.insert(file_id, IndexedFileState {
+                file_last_updated: file_last_updated.into_timestamp_millis(),
+                file_hash,
+                branch,
+            });
+        }
+        Ok(indexed_files)
+    }
+
+    fn doc_type_query(&self, doc_type: DocType) -> Box<dyn Query> {
+        let doc_type_field = self.schema.get_field(code_schema::CodeSchemaFields::DocType);
+        Box::new(TermQuery::new(Term::from_field_text(doc_type_field, doc_type.get_name()), IndexRecordOption::Basic))
+    }
+
+    /// The file document followed by the line chunk documents of the file.
+    fn create_documents(&self, data: CodeFileDto) -> Vec<tantivy::Document> {
+        let chunks = chunker::split_into_chunks(&data.file_content, &self.config.indexer.chunking);
+        let symbols = symbols::extract_symbols(&data.file_language, &data.file_content);
+        let mut docs = Vec::with_capacity(chunks.len() + 1);
+        for chunk in &chunks {
+            docs.push(self.schema.create_chunk_document(&data, chunk, &symbols));
+        }
+        docs.insert(0, self.schema.create_document(data, &symbols));
+        docs
+    }
+
+    fn adding_repo_files_to_index(&self, repo: &config::Repo, full_reindex: bool) -> Result<(), SearchError> {
+        let index_writer = self.index.writer(50_000_000)?;
+        let indexed_files = if full_reindex {
+            let repo_name_field = self.schema.get_field(code_schema::CodeSchemaFields::RepoName);
+            index_writer.delete_term(Term::from_field_text(repo_name_field, &repo.name));
+            HashMap::new()
+        } else {
+            self.load_indexed_files(repo)?
+        };
 
-        let walker = WalkDir::new(&repo.path).into_iter();
-        walker.filter_entry(|e| !self.filter_skip_patterns(e, repo))
+        let run = IndexingRun {
+            parent_id_field: self.schema.get_field(code_schema::CodeSchemaFields::ParentId),
+            index_writer: RwLock::new(index_writer),
+            seen_file_ids: Mutex::new(HashSet::with_capacity(indexed_files.len())),
+            indexed_files,
+            added_count: AtomicUsize::new(0),
+            updated_count: AtomicUsize::new(0),
+            skipped_files: Mutex::new(Vec::new()),
+        };
+        match repo.type_.as_str() {
+            "git" => self.index_git_repo_files(repo, &run)?,
+            _ => self.index_fs_repo_files(repo, &run)?,
+        }
+        let skipped_files = run.finish(repo)?;
+        self.skipped_files.write().unwrap().insert(repo.name.clone(), skipped_files);
+        if let Err(err) = self.import_precise_index(repo) {
+            log::error!("Failed to import precise index of repo {}: {}", repo.name, err);
+        }
+        Ok(())
+    }
+
+    /// Replaces the precise documents of a repo with those of its precise index dump. The
+    /// import is skipped while the dump is unchanged, and a repo without a dump loses them.
+    fn import_precise_index(&self, repo: &config::Repo) -> Result<(), SearchError> {
+        let searcher = self.index.reader()?.searcher();
+        let repo_name_field = self.schema.get_field(code_schema::CodeSchemaFields::RepoName);
+        let precise_query = BooleanQuery::new(vec![
+            (Occur::Must, Box::new(TermQuery::new(Term::from_field_text(repo_name_field, &repo.name), IndexRecordOption::Basic))),
+            (Occur::Must, self.doc_type_query(DocType::Precise)),
+        ]);
+        let imported_hash = match searcher.search(&precise_query, &TopDocs::with_limit(1))?.first() {
+            Some((_, doc_address)) => Some(self.schema.extract_hash_field(&searcher.doc(*doc_address)?)?),
+            None => None,
+        };
+
+        let documents = match (&repo.precise_index, repo.precise_index_path()) {
+            (Some(precise_index), Some(path)) => {
+                let bytes = fs::read(&path)?;
+                let dump_hash = format!("{:x}", Sha256::digest(&bytes));
+                if imported_hash.as_ref() == Some(&dump_hash) {
+                    log::info!("Precise index of repo {} is unchanged", repo.name);
+                    return Ok(());
+                }
+                let documents = match precise_index.format {
+                    PreciseIndexFormat::Scip => code_intel::read_scip(&repo.name, &bytes)?,
+                    PreciseIndexFormat::Lsif => code_intel::read_lsif(&repo.name, &repo.path, &String::from_utf8_lossy(&bytes))?,
+                };
+                Some((documents, dump_hash))
+            }
+            _ => None,
+        };
+        if imported_hash.is_none() && documents.is_none() {
+            return Ok(());
+        }
+
+        let mut index_writer = self.index.writer(50_000_000)?;
+        index_writer.delete_query(Box::new(precise_query))?;
+        match documents {
+            Some((documents, dump_hash)) => {
+                for document in &documents {
+                    index_writer.add_document(self.schema.create_precise_document(&repo.name, document, &dump_hash))?;
+                }
+                log::info!("Imported precise index of repo {}: {} files", repo.name, documents.len());
+            }
+            None => log::info!("Removing precise index of repo {}", repo.name),
+        }
+        index_writer.commit()?;
+        Ok(())
+    }
+
+    fn index_fs_repo_files(&self, repo: &config::Repo, run: &IndexingRun) -> Result<(), SearchError> {
+        let file_filter = RepoFileFilter::new(repo)?;
+        repo_walker::build_walker(repo, &file_filter, Path::new(&repo.path))
             .filter_map(|e| e.ok())
             .par_bridge()
             .for_each(|entry| {
-                match entry.path().extension() {
-                    Some(ext) => {
-                        if !repo.allowed_file_extensions.contains(&ext.to_str().unwrap().to_string()) {
-                            return;
-                        }
-                    }
-                    None => {
+                let is_file = entry.file_type().is_some_and(|file_type| file_type.is_file());
+                let relative_path = entry.path().strip_prefix(&repo.path).unwrap();
+                if !is_file || !file_filter.is_included(relative_path) {
+                    return;
+                }
+
+                let file_path = relative_path.to_string_lossy().to_string();
+                let file_id = CodeFileSchema::create_file_id(&repo.name, &file_path);
+                let indexed_file = run.mark_seen(&file_id);
+                let file_last_updated = entry.metadata()
+                    .map_err(|err| SkipReason::Unreadable(err.to_string()))
+                    .and_then(|metadata| Ok(chrono::DateTime::<chrono::Utc>::from(metadata.modified()?)));
+                let file_last_updated = match file_last_updated {
+                    Ok(file_last_updated) => file_last_updated,
+                    Err(reason) => return run.skip_file(&file_id, reason),
+                };
+                if let Some(indexed_file) = indexed_file {
+                    if indexed_file.file_last_updated == file_last_updated.timestamp_millis() {
                         return;
                     }
                 }
 
                 log::trace!("Indexing file: {:?}", entry.path());
+                let data = match self.read_repo_file(repo, entry.path(), file_id.clone(), file_path) {
+                    Ok(data) => data,
+                    Err(reason) => return run.skip_file(&file_id, reason),
+                };
+                if let Some(indexed_file) = indexed_file {
+                    if indexed_file.file_hash == data.file_hash {
+                        return;
+                    }
+                }
+                run.write_documents(&file_id, self.create_documents(data));
+            });
+        Ok(())
+    }
+
+    /// Indexes the trees committed at the repo's refs. Each distinct version of a file is
+    /// stored once and lists every branch it appears on, and since the blob id is part of
+    /// the file id, unchanged files are detected without reading their content. A file's
+    /// commit and modification time are those of the last commit that changed it, which
+    /// stay the same while the file does.
+    fn index_git_repo_files(&self, repo: &config::Repo, run: &IndexingRun) -> Result<(), SearchError> {
+        let file_filter = RepoFileFilter::new(repo)?;
+        let git_repo = git_repo::open_repository(&repo.path)?;
+        let mut files: HashMap<(String, Oid), GitIndexedFile> = HashMap::new();
+        for git_ref in repo.git_refs() {
+            let snapshot = git_repo::read_tree_snapshot(&git_repo, git_ref, |path, is_dir| {
+                file_filter.is_excluded(Path::new(path), is_dir)
+            })?;
+            log::info!("Indexing git repo {} at {} ({})", repo.name, git_ref, snapshot.commit_sha);
+
+            for file in snapshot.files {
+                if !file_filter.is_included(Path::new(&file.path)) {
+                    continue;
+                }
+                files.entry((file.path, file.blob_id))
+                    .or_insert_with(|| GitIndexedFile {
+                        commit_sha: snapshot.commit_sha.clone(),
+                        commit_time: snapshot.commit_time,
+                        branch: vec![],
+                    })
+                    .branch.push(git_ref.to_string());
+            }
+        }
+
+        // Looking up when each file last changed walks the history, so it is only done for
+        // the files that get indexed, and kept for as long as their ref does not move
+        let mut pending_paths: HashMap<&str, HashSet<&str>> = HashMap::new();
+        for ((file_path, blob_id), file) in &files {
+            let file_id = CodeFileSchema::create_git_file_id(&repo.name, file_path, &blob_id.to_string());
+            if file.needs_indexing(run.indexed_files.get(&file_id)) {
+                pending_paths.entry(&file.commit_sha).or_default().insert(file_path);
+            }
+        }
+        let mut cached_last_commits = self.last_commits.lock().unwrap().remove(&repo.name).unwrap_or_default();
+        let mut last_commits = HashMap::new();
+        for (commit_sha, paths) in pending_paths {
+            let mut commit_last_commits = cached_last_commits.remove(commit_sha).unwrap_or_default();
+            git_repo::read_last_commits(&git_repo, commit_sha, &paths, &mut commit_last_commits)?;
+            last_commits.insert(commit_sha.to_string(), commit_last_commits);
+        }
+
+        files.par_iter()
+            .for_each_init(|| git_repo::open_repository(&repo.path), |git_repo, ((file_path, blob_id), file)| {
+                let file_hash = blob_id.to_string();
+                let file_id = CodeFileSchema::create_git_file_id(&repo.name, file_path, &file_hash);
+                if !file.needs_indexing(run.mark_seen(&file_id)) {
+                    return;
+                }
 
-                let repo_name = repo.name.clone();
-                let repo_path = repo.path.clone();
-                let repo_type = repo.type_.clone();
-                let file_name = entry.file_name().to_str().unwrap().to_string();
-                let file_path = entry.path().strip_prefix(&repo.path).unwrap().to_str().unwrap().to_string();
-                let file_ext = match entry.path().extension() {
-                    Some(ext) => ext.to_str().unwrap().to_string(),
-                    None => "".to_string(),
+                let git_repo = match git_repo {
+                    Ok(git_repo) => git_repo,
+                    Err(err) => {
+                        log::error!("Failed to open git repo {}: {}", repo.name, err);
+                        return;
+                    }
                 };
-                let file_size = entry.metadata().unwrap().len();
-                let file_last_updated = entry.metadata().unwrap().modified().unwrap();
-                let file_language = mime_guess::from_path(&entry.path()).first_or_octet_stream().to_string();
-                let file_content = fs::read_to_string(entry.path()).unwrap();
-
-                let data = crate::search::code_schema::CodeFileDto {
-                    repo_name,
-                    repo_path,
-                    repo_type,
-                    file_name,
-                    file_path,
-                    file_ext,
-                    file_size,
-                    file_last_updated: file_last_updated.into(),
-                    file_language,
-                    file_content,
+                let file_content = git_repo::read_blob_size(git_repo, *blob_id)
+                    .map_err(|err| SkipReason::Unreadable(err.to_string()))
+                    .and_then(|file_size| {
+                        let read_plan = ReadPlan::for_file_size(file_size, repo.file_size_limit(&self.config.indexer))?;
+                        let file_content = match read_plan {
+                            ReadPlan::Nothing => Vec::new(),
+                            _ => git_repo::read_blob(git_repo, *blob_id)
+                                .map_err(|err| SkipReason::Unreadable(err.to_string()))?,
+                        };
+                        Ok((file_size, read_plan, file_content))
+                    });
+                let (file_size, read_plan, mut file_content) = match file_content {
+                    Ok(file_content) => file_content,
+                    Err(reason) => return run.skip_file(&file_id, reason),
                 };
-                let doc = self.schema.create_document(data);
+                if let ReadPlan::Prefix(max_file_size) = read_plan {
+                    file_content.truncate(max_file_size as usize);
+                }
 
-                let index_writer = index_writer_arc.read().unwrap();
-                index_writer.add_document(doc).unwrap();
+                log::trace!("Indexing file: {}", file_id);
+                let (commit_sha, file_last_updated) = match last_commits.get(&file.commit_sha).and_then(|last_commits| last_commits.get(file_path)) {
+                    Some(last_commit) => (last_commit.sha.clone(), last_commit.time),
+                    None => (file.commit_sha.clone(), file.commit_time),
+                };
+                let data = self.create_file_dto(repo, file_id.clone(), file_path.clone(), file_content, file_size, &read_plan, file_last_updated, file_hash);
+                let data = match data {
+                    Ok(data) => data,
+                    Err(reason) => return run.skip_file(&file_id, reason),
+                };
+                let data = CodeFileDto {
+                    commit_sha,
+                    branch: file.branch.clone(),
+                    ..data
+                };
+                run.write_documents(&file_id, self.create_documents(data));
             });
+        self.last_commits.lock().unwrap().insert(repo.name.clone(), last_commits);
+        Ok(())
+    }
+
+    /// Brings the index of a repo up to date with its current state on disk or at its ref.
+    pub(crate) fn sync_repo(&self, repo: &config::Repo) -> Result<(), SearchError> {
+        self.adding_repo_files_to_index(repo, false)
+    }
+
+    /// Re-indexes or removes the given paths of a repo in a single commit. Paths that no
+    /// longer exist on disk, or that became ignored or excluded, are removed from the
+    /// index, existing files are re-added.
+    pub(crate) fn apply_file_changes(&self, repo: &config::Repo, paths: &[PathBuf]) -> Result<(), SearchError> {
+        let file_filter = RepoFileFilter::new(repo)?;
+        let mut index_writer = self.index.writer(50_000_000)?;
+        let mut changed_file_ids = Vec::with_capacity(paths.len());
+        let mut skipped_files = Vec::new();
+
+        for path in paths {
+            let relative_path = match path.strip_prefix(&repo.path) {
+                Ok(relative_path) => relative_path,
+                Err(_) => continue,
+            };
+            let file_path = relative_path.to_string_lossy().to_string();
+            changed_file_ids.push(CodeFileSchema::create_file_id(&repo.name, file_path.trim_end_matches('/')));
+            if file_filter.is_excluded(relative_path, path.is_dir()) || repo_walker::is_ignored(repo, path) {
+                self.delete_path(&index_writer, repo, &file_path)?;
+                continue;
+            }
+
+            if path.is_dir() {
+                let files = repo_walker::build_walker(repo, &file_filter, path)
+                    .filter_map(|e| e.ok())
+                    .filter(|e| e.file_type().is_some_and(|file_type| file_type.is_file()))
+                    .map(|e| e.into_path())
+                    .collect::<Vec<_>>();
+                for file in files {
+                    self.upsert_file(&index_writer, repo, &file_filter, &file, &mut skipped_files)?;
+                }
+                continue;
+            }
+
+            if path.is_file() {
+                self.upsert_file(&index_writer, repo, &file_filter, path, &mut skipped_files)?;
+            } else {
+                self.delete_path(&index_writer, repo, &file_path)?;
+            }
+        }
 
-        let mut index_writer = index_writer_arc.write().unwrap();
         index_writer.commit()?;
+        self.update_skipped_files(repo, &changed_file_ids, skipped_files);
+        Ok(())
+    }
+
+    /// Replaces what is recorded as skipped for the changed files, or for the files below
+    /// changed directories, with the files the change left out.
+    fn update_skipped_files(&self, repo: &config::Repo, changed_file_ids: &[String], skipped_files: Vec<SkippedFile>) {
+        log_skipped_files(&repo.name, &skipped_files);
+        let mut all_skipped_files = self.skipped_files.write().unwrap();
+        let repo_skipped_files = all_skipped_files.entry(repo.name.clone()).or_default();
+        repo_skipped_files.retain(|skipped_file| {
+            !changed_file_ids.iter().any(|file_id| {
+                skipped_file.file_id.strip_prefix(file_id.as_str()).is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+            })
+        });
+        repo_skipped_files.extend(skipped_files);
+    }
+
+    /// Removes a deleted file, or every file below a deleted directory, from the index.
+    /// A removed path can be either, so both the exact id and the id prefix are deleted.
+    fn delete_path(&self, index_writer: &IndexWriter, repo: &config::Repo, file_path: &str) -> Result<(), SearchError> {
+        let parent_id_field = self.schema.get_field(code_schema::CodeSchemaFields::ParentId);
+        let file_id = CodeFileSchema::create_file_id(&repo.name, file_path.trim_end_matches('/'));
+        log::debug!("Removing path from index: {}", file_id);
+        index_writer.delete_term(Term::from_field_text(parent_id_field, &file_id));
+
+        // '0' is the character right after '/', so this range covers every id below the directory
+        let dir_prefix = format!("{}/", file_id);
+        let dir_end = format!("{}0", file_id);
+        let dir_query = RangeQuery::new_str_bounds(
+            code_schema::CodeSchemaFields::ParentId.get_name().to_string(),
+            Bound::Included(&dir_prefix),
+            Bound::Excluded(&dir_end),
+        );
+        index_writer.delete_query(Box::new(dir_query))?;
+        Ok(())
+    }
+
+    fn find_file_document(&self, searcher: &tantivy::Searcher, file_id: &str) -> Result<Option<tantivy::Document>, SearchError> {
+        match self.find_file_address(searcher, file_id)? {
+            Some(doc_address) => Ok(Some(searcher.doc(doc_address)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn find_file_address(&self, searcher: &tantivy::Searcher, file_id: &str) -> Result<Option<DocAddress>, SearchError> {
+        let id_field = self.schema.get_field(code_schema::CodeSchemaFields::FileId);
+        let query = TermQuery::new(Term::from_field_text(id_field, file_id), IndexRecordOption::Basic);
+        let top_docs = searcher.search(&query, &tantivy::collector::TopDocs::with_limit(1))?;
+        Ok(top_docs.first().map(|(_, doc_address)| *doc_address))
+    }
 
+    fn upsert_file(
+        &self,
+        index_writer: &IndexWriter,
+        repo: &config::Repo,
+        file_filter: &RepoFileFilter,
+        path: &Path,
+        skipped_files: &mut Vec<SkippedFile>,
+    ) -> Result<(), SearchError> {
+        let relative_path = path.strip_prefix(&repo.path).unwrap();
+        if !file_filter.is_included(relative_path) {
+            return Ok(());
+        }
+        let file_path = relative_path.to_string_lossy().to_string();
+        let file_id = CodeFileSchema::create_file_id(&repo.name, &file_path);
+        log::debug!("Re-indexing file: {}", file_id);
+
+        let parent_id_field = self.schema.get_field(code_schema::CodeSchemaFields::ParentId);
+        index_writer.delete_term(Term::from_field_text(parent_id_field, &file_id));
+        match self.read_repo_file(repo, path, file_id.clone(), file_path) {
+            Ok(data) => {
+                for doc in self.create_documents(data) {
+                    index_writer.add_document(doc)?;
+                }
+            }
+            Err(reason) => skipped_files.push(skipped_file(&file_id, reason)),
+        }
         Ok(())
     }
 }
 
 
-#[async_trait]
-impl SearchEngine for FileSearchEngine {
-    async fn search(&self, options: SearchOptions) -> Result<Vec<ResultItem>, SearchError> {
-        log::info!("Executing search with query: {}", options.query);
+/// A document with its sort key and the score adjusted by the ranker.
+type RankedDoc = ((SortKey, f32), DocAddress);
+
+/// How many of a file's matching chunks are listed under its result.
+const MAX_CHUNKS_PER_RESULT: usize = 5;
+
+impl FileSearchEngine {
+    fn search_text(&self, searcher: &tantivy::Searcher, parsed_query: &ParsedQuery, options: &SearchOptions, cursor: Option<&Cursor>) -> Result<SearchResults, SearchError> {
         let index = &self.index;
-        let index_reader = index.reader()?;
-        let searcher = index_reader.searcher();
-
-        let query = options.query;
-        let limit = options.limit;
-        let query_parser = tantivy::query::QueryParser::for_index(&index, vec![
-            self.schema.get_field(code_schema::CodeSchemaFields::FileContent),
-            self.schema.get_field(code_schema::CodeSchemaFields::FileName),
-            self.schema.get_field(code_schema::CodeSchemaFields::FilePath),
-        ]);
+        let start = cursor.map_or(options.offset, |cursor| cursor.position);
+        let now = ranking_time(cursor);
+        let ranking = &self.config.ranking;
+        let field_boosts = [
+            (self.schema.get_field(code_schema::CodeSchemaFields::FileContent), ranking.content_boost),
+            (self.schema.get_field(code_schema::CodeSchemaFields::FileName), ranking.file_name_boost),
+            (self.schema.get_field(code_schema::CodeSchemaFields::FilePath), ranking.file_path_boost),
+            (self.schema.get_field(code_schema::CodeSchemaFields::ChunkContent), ranking.content_boost),
+        ];
+        let mut query_parser = tantivy::query::QueryParser::for_index(index, field_boosts.iter().map(|(field, _)| *field).collect());
+        for (field, boost) in field_boosts {
+            query_parser.set_field_boost(field, boost);
+        }
+
+        let query = if parsed_query.text.is_empty() {
+            self.doc_type_query(DocType::File)
+        } else {
+            query_parser.parse_query(&parsed_query.text)
+                .map_err(|err| SearchError::from((parsed_query.text.as_str(), err)).within_query(parsed_query))?
+        };
+        let content_field = self.schema.get_field(code_schema::CodeSchemaFields::FileContent);
+        let mut snippet_generator = SnippetGenerator::new(
+            query.as_ref(),
+            &[content_field, self.schema.get_field(code_schema::CodeSchemaFields::ChunkContent)],
+            index.tokenizer_for_field(content_field)?,
+            options.context_lines,
+        );
+        let query = self.with_filters(query, parsed_query)?;
 
-        let query = query_parser.parse_query(&query)?;
-        let top_docs = searcher.search(&query, &tantivy::collector::TopDocs::with_limit(limit))?;
+        // The results are the files matching the query as a whole. Chunks are matched on
+        // their own text, so a chunk hit only counts when its file is among them, otherwise
+        // `-banana` would not exclude a file that has it in another chunk
+        let file_query = BooleanQuery::new(vec![
+            (Occur::Must, query.box_clone()),
+            (Occur::Must, Box::new(ConstScoreQuery::new(self.doc_type_query(DocType::File), 0.0))),
+        ]);
+        let facet_collector = AggregationCollector::from_aggs(facets::create_facet_aggregations(), Default::default());
+        let (matching_files, facet_results, directory_counts) = searcher.search(&file_query, &(DocSetCollector, facet_collector, facets::create_directory_collector()))?;
+        let total = matching_files.len();
 
+        let fetch_limit = (start + options.limit).max(1);
+        // Files are ranked and paged on their own, the chunks that matched are looked up for
+        // the files of the page afterwards
+        let top_docs = self.top_files(searcher, &file_query, fetch_limit, options.sort_by, now)?;
         let mut results = Vec::new();
+        let best_hits = self.add_hits(searcher, without_sort_keys(top_docs), &matching_files, &mut results)?;
+        let mut page = paginate(results, total, options, cursor, now);
+        let parent_id_field = self.schema.get_field(code_schema::CodeSchemaFields::ParentId);
+        for file_id in page.results.iter().map(|result| result.data.file_id.clone()).collect::<Vec<_>>() {
+            let chunk_query = BooleanQuery::new(vec![
+                (Occur::Must, query.box_clone()),
+                (Occur::Must, self.doc_type_query(DocType::Chunk)),
+                (Occur::Must, Box::new(TermQuery::new(Term::from_field_text(parent_id_field, &file_id), IndexRecordOption::Basic))),
+            ]);
+            let chunk_docs = searcher.search(&chunk_query, &self.ranked_top_docs(MAX_CHUNKS_PER_RESULT, SortBy::Score, now))?;
+            self.add_hits(searcher, without_sort_keys(chunk_docs), &matching_files, &mut page.results)?;
+        }
+        page.facets = Facets::from_index(&facet_results, &directory_counts, total as u64);
+        let symbol_matcher = create_symbol_matcher(parsed_query)?;
+        for result in &mut page.results {
+            if let Some(symbol_matcher) = &symbol_matcher {
+                if let Some(file_doc) = self.find_file_document(searcher, &result.data.file_id)? {
+                    result.symbols = self.schema.extract_symbols(&file_doc).into_iter()
+                        .filter(|symbol| symbol_matcher.is_match(&symbol.name))
+                        .collect();
+                }
+            }
+            if let (true, Some(doc_address)) = (options.explain, best_hits.get(&result.data.file_id)) {
+                let explanation = query.explain(searcher, *doc_address)?;
+                let factors = self.ranker.factors(&result.data.file_path, result.data.file_last_updated.timestamp_millis(), now);
+                let details = serde_json::to_value(&explanation).ok();
+                result.explanation = Some(ScoreExplanation::new(explanation.value(), factors, details));
+            }
+            result.snippets = snippet_generator.generate(&result.data.file_content);
+            if result.snippets.is_empty() && !result.symbols.is_empty() {
+                let name_ranges = symbols::name_ranges(&result.data.file_content, &result.symbols);
+                result.snippets = snippet::build_snippets_from_ranges(&result.data.file_content, &name_ranges, options.context_lines);
+            }
+            if !options.include_content {
+                result.data.file_content.clear();
+            }
+        }
+        Ok(page)
+    }
+
+    /// Adds file and chunk hits to the results, grouped by file. Hits come best first, so a
+    /// new file is ranked by its best hit, be it the whole file or one of its chunks. Chunks
+    /// of files that are not among the matching files are dropped. Returns the best hit of
+    /// each file added.
+    fn add_hits(
+        &self,
+        searcher: &tantivy::Searcher,
+        hits: Vec<(f32, DocAddress)>,
+        matching_files: &HashSet<DocAddress>,
+        results: &mut Vec<ResultItem>,
+    ) -> Result<HashMap<String, DocAddress>, SearchError> {
+        let mut best_hits = HashMap::new();
+        let mut result_positions: HashMap<String, usize> = results.iter()
+            .enumerate()
+            .map(|(position, result)| (result.data.file_id.clone(), position))
+            .collect();
+        for (score, doc_address) in hits {
+            let retrieved_doc = searcher.doc(doc_address)?;
+            let parent_id = self.schema.extract_text_field(&retrieved_doc, code_schema::CodeSchemaFields::ParentId)?;
+            let doc_type = self.schema.extract_text_field(&retrieved_doc, code_schema::CodeSchemaFields::DocType)?;
+            let is_chunk = doc_type == DocType::Chunk.get_name();
+
+            let position = match result_positions.get(&parent_id) {
+                Some(position) => *position,
+                None => {
+                    let file_doc = if is_chunk {
+                        match self.find_file_address(searcher, &parent_id)? {
+                            Some(file_address) if matching_files.contains(&file_address) => searcher.doc(file_address)?,
+                            _ => continue,
+                        }
+                    } else {
+                        retrieved_doc.clone()
+                    };
+                    results.push(ResultItem {
+                        data: self.schema.create_code_file_dto(&file_doc)?,
+                        _score: score,
+                        chunks: vec![],
+                        snippets: vec![],
+                        symbols: vec![],
+                        explanation: None,
+                    });
+                    best_hits.insert(parent_id.clone(), doc_address);
+                    result_positions.insert(parent_id, results.len() - 1);
+                    results.len() - 1
+                }
+            };
 
-        for (score, doc_address) in top_docs {
-            let retrieved_doc = searcher.doc(doc_address.clone()).unwrap();
-            let code_file_dto = self.schema.create_code_file_dto(&retrieved_doc).unwrap();
+            if is_chunk {
+                let start_line = self.schema.extract_u64_field(&retrieved_doc, code_schema::CodeSchemaFields::ChunkStartLine)?;
+                let end_line = self.schema.extract_u64_field(&retrieved_doc, code_schema::CodeSchemaFields::ChunkEndLine)?;
+                results[position].chunks.push(MatchedChunk {
+                    _score: score,
+                    start_line: start_line as usize,
+                    end_line: end_line as usize,
+                });
+            }
+        }
+        Ok(best_hits)
+    }
+
+    fn search_literal(&self, searcher: &tantivy::Searcher, parsed_query: &ParsedQuery, options: &SearchOptions, cursor: Option<&Cursor>) -> Result<SearchResults, SearchError> {
+        let regex = regex::RegexBuilder::new(&regex::escape(&parsed_query.text))
+            .case_insensitive(!parsed_query.case_sensitive)
+            .build()?;
+        let trigram_query = TrigramQuery::literal(&parsed_query.text);
+        self.search_trigram_candidates(searcher, &trigram_query, parsed_query, options, cursor, &regex)
+    }
+
+    fn search_regex(&self, searcher: &tantivy::Searcher, parsed_query: &ParsedQuery, options: &SearchOptions, cursor: Option<&Cursor>) -> Result<SearchResults, SearchError> {
+        let (regex, trigram_query) = regex_query::compile(&parsed_query.text, parsed_query.case_sensitive)
+            .map_err(|err| err.within_query(parsed_query))?;
+        log::debug!("Regex candidates query: {:?}", trigram_query);
+        self.search_trigram_candidates(searcher, &trigram_query, parsed_query, options, cursor, &regex)
+    }
+
+    /// Narrows the files down to those satisfying the trigram query, then looks for exact
+    /// matches in their stored content. Files are ranked by their match count, adjusted by
+    /// the ranker. Queries without trigrams, like `.*` or a two letter literal, would scan
+    /// every file and are rejected.
+    fn search_trigram_candidates(
+        &self,
+        searcher: &tantivy::Searcher,
+        trigram_query: &TrigramQuery,
+        parsed_query: &ParsedQuery,
+        options: &SearchOptions,
+        cursor: Option<&Cursor>,
+        regex: &regex::Regex,
+    ) -> Result<SearchResults, SearchError> {
+        let now = ranking_time(cursor);
+        if parsed_query.text.is_empty() {
+            return Ok(paginate(vec![], 0, options, cursor, now));
+        }
+        let Some(candidate_query) = self.create_trigram_query(trigram_query) else {
+            return Err(SearchError::invalid_query_at(
+                "The search needs at least 3 literal characters in a row to narrow down the files",
+                parsed_query.query_position(0),
+            ));
+        };
+        let candidate_query = self.with_filters(candidate_query, parsed_query)?;
+        let doc_addresses = searcher.search(&candidate_query, &DocSetCollector)?;
+        let symbol_matcher = create_symbol_matcher(parsed_query)?;
+
+        let mut results = Vec::new();
+        for doc_address in doc_addresses {
+            let retrieved_doc = searcher.doc(doc_address)?;
+            let mut data = self.schema.create_code_file_dto(&retrieved_doc)?;
+            let matches = regex.find_iter(&data.file_content)
+                .filter(|matched| !matched.is_empty())
+                .map(|matched| matched.range())
+                .collect::<Vec<_>>();
+            if matches.is_empty() {
+                continue;
+            }
+            let snippets = snippet::build_snippets_from_ranges(&data.file_content, &matches, options.context_lines);
+            if !options.include_content {
+                data.file_content.clear();
+            }
+            let symbols = match &symbol_matcher {
+                Some(symbol_matcher) => self.schema.extract_symbols(&retrieved_doc).into_iter()
+                    .filter(|symbol| symbol_matcher.is_match(&symbol.name))
+                    .collect(),
+                None => vec![],
+            };
+            let factors = self.ranker.factors(&data.file_path, data.file_last_updated.timestamp_millis(), now);
+            let base_score = matches.len() as f32;
             results.push(ResultItem {
-                data: code_file_dto,
-                _score: score,
+                _score: factors.apply(base_score),
+                data,
+                chunks: vec![],
+                snippets,
+                symbols,
+                explanation: options.explain.then(|| ScoreExplanation::new(base_score, factors, None)),
             });
         }
 
-        Ok(results)
+        let total = results.len();
+        let facets = Facets::from_results(&results);
+        let mut page = paginate(results, total, options, cursor, now);
+        page.facets = facets;
+        Ok(page)
+    }
+
+    /// The identifier at a 1-based line and column of an indexed file.
+    fn identifier_at_position(&self, searcher: &tantivy::Searcher, repo: &str, path: &str, line: usize, column: usize) -> Result<String, SearchError> {
+        let query = self.path_query(repo, path, DocType::File);
+        let top_docs = searcher.search(&query, &TopDocs::with_limit(1))?;
+        let Some((_, doc_address)) = top_docs.first() else {
+            return Err(SearchError::not_found(format!("File {} not found in repo {}", path, repo)));
+        };
+        let data = self.schema.create_code_file_dto(&searcher.doc(*doc_address)?)?;
+        line.checked_sub(1)
+            .and_then(|index| data.file_content.lines().nth(index))
+            .and_then(|text| references::identifier_at(text, column))
+            .map(|identifier| identifier.to_string())
+            .ok_or_else(|| SearchError::not_found(format!("No identifier at {}:{}:{}", path, line, column)))
+    }
+
+    /// Matches the document of the given type for a path of a repo.
+    fn path_query(&self, repo: &str, path: &str, doc_type: DocType) -> BooleanQuery {
+        let term_query = |field: code_schema::CodeSchemaFields, value: &str| -> (Occur, Box<dyn Query>) {
+            let term = Term::from_field_text(self.schema.get_field(field), value);
+            (Occur::Must, Box::new(TermQuery::new(term, IndexRecordOption::Basic)))
+        };
+        BooleanQuery::new(vec![
+            term_query(code_schema::CodeSchemaFields::RepoName, repo),
+            term_query(code_schema::CodeSchemaFields::FilePathRaw, path),
+            (Occur::Must, self.doc_type_query(doc_type)),
+        ])
+    }
+
+    /// The precise documents, with their repo, in which the symbol occurs or, for the
+    /// `precise_definitions` field, is defined.
+    fn find_precise_documents(&self, searcher: &tantivy::Searcher, field: code_schema::CodeSchemaFields, symbol: &str) -> Result<Vec<(String, PreciseDocument)>, SearchError> {
+        let query = TermQuery::new(Term::from_field_text(self.schema.get_field(field), symbol), IndexRecordOption::Basic);
+        searcher.search(&query, &DocSetCollector)?
+            .into_iter()
+            .map(|doc_address| {
+                let doc = searcher.doc(doc_address)?;
+                let repo_name = self.schema.extract_text_field(&doc, code_schema::CodeSchemaFields::RepoName)?;
+                Ok((repo_name, self.schema.extract_precise_document(&doc)?))
+            })
+            .collect()
+    }
+
+    /// The file documents with a symbol of that name, ignoring case like the symbol index.
+    fn find_definition_documents(&self, searcher: &tantivy::Searcher, name: &str) -> Result<Vec<tantivy::Document>, SearchError> {
+        let symbol_names_field = self.schema.get_field(code_schema::CodeSchemaFields::SymbolNames);
+        let query = BooleanQuery::new(vec![
+            (Occur::Must, Box::new(TermQuery::new(Term::from_field_text(symbol_names_field, &name.to_lowercase()), IndexRecordOption::Basic)) as Box<dyn Query>),
+            (Occur::Must, self.doc_type_query(DocType::File)),
+        ]);
+        searcher.search(&query, &DocSetCollector)?
+            .into_iter()
+            .map(|doc_address| Ok(searcher.doc(doc_address)?))
+            .collect()
+    }
+
+    /// The files the identifier occurs in, with the 1-based lines it occurs on. These are read
+    /// from the positions of the identifier index, which are line numbers.
+    fn find_identifier_lines(&self, searcher: &tantivy::Searcher, name: &str) -> Result<Vec<(DocAddress, BTreeSet<usize>)>, SearchError> {
+        let identifiers_field = self.schema.get_field(code_schema::CodeSchemaFields::Identifiers);
+        let term = Term::from_field_text(identifiers_field, name);
+        let mut occurrences = Vec::new();
+        for (segment_ord, segment_reader) in searcher.segment_readers().iter().enumerate() {
+            let inverted_index = segment_reader.inverted_index(identifiers_field)?;
+            let Some(mut postings) = inverted_index.read_postings(&term, IndexRecordOption::WithFreqsAndPositions)? else {
+                continue;
+            };
+            let mut positions = Vec::new();
+            while postings.doc() != TERMINATED {
+                let doc = postings.doc();
+                if !segment_reader.is_deleted(doc) {
+                    postings.positions(&mut positions);
+                    let lines = positions.iter().map(|position| *position as usize + 1).collect();
+                    occurrences.push((DocAddress::new(segment_ord as u32, doc), lines));
+                }
+                postings.advance();
+            }
+        }
+        Ok(occurrences)
+    }
+
+    /// The best `limit` files, plus the files tied with the last of them. Pages order equal
+    /// keys by file id, so a cut through a tie would drop files from every page.
+    fn top_files(
+        &self,
+        searcher: &tantivy::Searcher,
+        query: &dyn Query,
+        limit: usize,
+        sort_by: SortBy,
+        now: i64,
+    ) -> Result<Vec<RankedDoc>, SearchError> {
+        let mut fetch_limit = limit;
+        loop {
+            let top_docs = searcher.search(query, &self.ranked_top_docs(fetch_limit, sort_by, now))?;
+            let boundary_key = top_docs.get(limit - 1).map(|((key, _), _)| key);
+            let last_key = top_docs.last().map(|((key, _), _)| key);
+            if top_docs.len() < fetch_limit || boundary_key != last_key {
+                return Ok(top_docs);
+            }
+            fetch_limit *= 2;
+        }
+    }
+
+    /// Collects the best documents by the sort key, along with their score adjusted by the
+    /// ranker. The path, size and modification time are read from the fast fields.
+    fn ranked_top_docs(&self, limit: usize, sort_by: SortBy, now: i64) -> impl Collector<Fruit = Vec<RankedDoc>> {
+        let ranker = self.ranker.clone();
+        TopDocs::with_limit(limit).tweak_score(move |segment_reader: &SegmentReader| {
+            let ranker = ranker.clone();
+            let fast_fields = segment_reader.fast_fields();
+            let sizes = fast_fields.u64(code_schema::CodeSchemaFields::FileSize.get_name()).ok();
+            let dates = fast_fields.date(code_schema::CodeSchemaFields::FileLastUpdated.get_name()).ok();
+            let paths = fast_fields.str(code_schema::CodeSchemaFields::FilePathRaw.get_name()).ok().flatten();
+            move |doc: DocId, score: f32| {
+                let mut path = String::new();
+                if let Some(paths) = &paths {
+                    if let Some(ord) = paths.term_ords(doc).next() {
+                        let _ = paths.ord_to_str(ord, &mut path);
+                    }
+                }
+                let modified = dates.as_ref()
+                    .and_then(|dates| dates.first(doc))
+                    .map_or(0, |date| date.into_timestamp_millis());
+                let score = ranker.factors(&path, modified, now).apply(score);
+                let key = match sort_by {
+                    SortBy::Score => SortKey::Score(score),
+                    SortBy::Size => SortKey::Size(sizes.as_ref().and_then(|sizes| sizes.first(doc)).unwrap_or(0)),
+                    SortBy::Modified => SortKey::Modified(modified),
+                    SortBy::Path => SortKey::Path(path),
+                };
+                (key, score)
+            }
+        })
+    }
+
+    /// Restricts a query to the documents matching the filters of the parsed query. Filters of
+    /// the same kind are alternatives, negated filters exclude what they match.
+    fn with_filters(&self, query: Box<dyn Query>, parsed_query: &ParsedQuery) -> Result<Box<dyn Query>, SearchError> {
+        if parsed_query.filters.is_empty() {
+            return Ok(query);
+        }
+        let mut clauses = vec![(Occur::Must, query)];
+        let mut alternatives: Vec<(FilterKind, Vec<Box<dyn Query>>)> = Vec::new();
+        for filter in &parsed_query.filters {
+            let filter_query = self.create_filter_query(filter, parsed_query.case_sensitive)?;
+            if filter.negated {
+                clauses.push((Occur::MustNot, filter_query));
+                continue;
+            }
+            match alternatives.iter_mut().find(|(kind, _)| *kind == filter.kind) {
+                Some((_, queries)) => queries.push(filter_query),
+                None => alternatives.push((filter.kind, vec![filter_query])),
+            }
+        }
+        for (_, queries) in alternatives {
+            clauses.push((Occur::Must, Box::new(BooleanQuery::union(queries))));
+        }
+        Ok(Box::new(BooleanQuery::new(clauses)))
+    }
+
+    fn create_filter_query(&self, filter: &Filter, case_sensitive: bool) -> Result<Box<dyn Query>, SearchError> {
+        let (comparison, value) = match &filter.value {
+            FilterValue::Text(value) | FilterValue::Regex(value) => (Comparison::Equal, value.as_str()),
+            FilterValue::Range(comparison, value) => (*comparison, value.as_str()),
+        };
+        let term_query = |field: code_schema::CodeSchemaFields, value: &str| -> Box<dyn Query> {
+            let term = Term::from_field_text(self.schema.get_field(field), value);
+            Box::new(TermQuery::new(term, IndexRecordOption::Basic))
+        };
+        match filter.kind {
+            FilterKind::Repo => return Ok(term_query(code_schema::CodeSchemaFields::RepoName, value)),
+            FilterKind::Ext => return Ok(term_query(code_schema::CodeSchemaFields::FileExt, value.trim_start_matches('.'))),
+            FilterKind::Branch => return Ok(term_query(code_schema::CodeSchemaFields::Branch, value)),
+            FilterKind::Lang => {
+                let language = languages::canonical_name(value).unwrap_or(value);
+                return Ok(term_query(code_schema::CodeSchemaFields::FileLanguage, language));
+            }
+            FilterKind::Size => {
+                let size = crate::utils::parse_byte_size(value).ok_or_else(|| {
+                    SearchError::invalid_query(format!("Invalid size '{}', expected a size like 10kb", value))
+                })?;
+                let (lower, upper) = range_bounds(comparison, size, size.saturating_add(1));
+                let field_name = code_schema::CodeSchemaFields::FileSize.get_name().to_string();
+                return Ok(Box::new(RangeQuery::new_u64_bounds(field_name, lower, upper)));
+            }
+            FilterKind::Modified => {
+                let (from, to) = crate::utils::parse_date_span(value).ok_or_else(|| {
+                    SearchError::invalid_query(format!("Invalid date '{}', expected a date like 2026-01-01", value))
+                })?;
+                let (lower, upper) = range_bounds(
+                    comparison,
+                    crate::utils::convert_datetime_chrono_to_tantivy(&from),
+                    crate::utils::convert_datetime_chrono_to_tantivy(&to),
+                );
+                let field_name = code_schema::CodeSchemaFields::FileLastUpdated.get_name().to_string();
+                return Ok(Box::new(RangeQuery::new_date_bounds(field_name, lower, upper)));
+            }
+            FilterKind::Symbol => {
+                let symbol_names_field = self.schema.get_field(code_schema::CodeSchemaFields::SymbolNames);
+                return Ok(match &filter.value {
+                    FilterValue::Regex(pattern) => {
+                        Box::new(RegexQuery::from_pattern(&format!("(?i){}", anchor_path_pattern(pattern, ".*")), symbol_names_field)?)
+                    }
+                    _ => Box::new(TermQuery::new(Term::from_field_text(symbol_names_field, &value.to_lowercase()), IndexRecordOption::Basic)),
+                });
+            }
+            FilterKind::Path | FilterKind::File => {}
+        }
+
+        // Path regexes are matched against the whole indexed path, so unanchored patterns
+        // are padded to match anywhere in it
+        let case_flag = if case_sensitive { "" } else { "(?i)" };
+        let pattern = match (filter.kind, &filter.value) {
+            (FilterKind::Path, FilterValue::Text(path)) => format!(".*{}.*", regex::escape(path)),
+            (FilterKind::Path, FilterValue::Regex(pattern)) => anchor_path_pattern(pattern, ".*"),
+            (_, FilterValue::Text(name) | FilterValue::Range(_, name)) => format!("(.*/)?[^/]*{}[^/]*", regex::escape(name)),
+            (_, FilterValue::Regex(pattern)) => format!("(.*/)?{}", anchor_path_pattern(pattern, "[^/]*")),
+        };
+        let path_field = self.schema.get_field(code_schema::CodeSchemaFields::FilePathRaw);
+        Ok(Box::new(RegexQuery::from_pattern(&format!("{}{}", case_flag, pattern), path_field)?))
+    }
+
+    /// Translates a trigram query to the trigram field, `None` if it does not restrict anything.
+    fn create_trigram_query(&self, trigram_query: &TrigramQuery) -> Option<Box<dyn Query>> {
+        let trigrams_field = self.schema.get_field(code_schema::CodeSchemaFields::FileTrigrams);
+        match trigram_query {
+            TrigramQuery::All => None,
+            TrigramQuery::Trigram(trigram) => {
+                let term = Term::from_field_text(trigrams_field, trigram);
+                Some(Box::new(TermQuery::new(term, IndexRecordOption::Basic)))
+            }
+            TrigramQuery::And(queries) => {
+                let queries = queries.iter()
+                    .filter_map(|query| self.create_trigram_query(query))
+                    .collect::<Vec<_>>();
+                Some(Box::new(BooleanQuery::intersection(queries)))
+            }
+            TrigramQuery::Or(queries) => {
+                let queries = queries.iter()
+                    .map(|query| self.create_trigram_query(query))
+                    .collect::<Option<Vec<_>>>()?;
+                Some(Box::new(BooleanQuery::union(queries)))
+            }
+        }
+    }
+}
+
+/// The time to rank recency against, kept from the first page when paging with a cursor.
+fn ranking_time(cursor: Option<&Cursor>) -> i64 {
+    cursor.map_or_else(|| chrono::Utc::now().timestamp_millis(), |cursor| cursor.now)
+}
+
+fn without_sort_keys(top_docs: Vec<RankedDoc>) -> Vec<(f32, DocAddress)> {
+    top_docs.into_iter().map(|((_, score), doc_address)| (score, doc_address)).collect()
+}
+
+/// Picks the requested page from the results, ordered by the sort key and then file id so that
+/// pages are stable, and creates the cursor for the page after it, ranked against `now`.
+fn paginate(results: Vec<ResultItem>, total: usize, options: &SearchOptions, cursor: Option<&Cursor>, now: i64) -> SearchResults {
+    let mut results = results.into_iter()
+        .map(|result| (SortKey::for_result(options.sort_by, &result), result))
+        .collect::<Vec<_>>();
+    results.sort_by(|(a_key, a), (b_key, b)| {
+        b_key.partial_cmp(a_key).unwrap_or(std::cmp::Ordering::Equal).then_with(|| a.data.file_id.cmp(&b.data.file_id))
+    });
+    let (start, skipped) = match cursor {
+        Some(cursor) => {
+            let skipped = results.iter()
+                .take_while(|(key, result)| !cursor.is_before(key, &result.data.file_id))
+                .count();
+            (cursor.position, skipped)
+        }
+        None => (options.offset, options.offset),
+    };
+    let results = results.into_iter()
+        .skip(skipped)
+        .take(options.limit)
+        .collect::<Vec<_>>();
+
+    let end = start + results.len();
+    let next_cursor = match results.last() {
+        Some((key, last)) if end < total => Some(Cursor {
+            key: key.clone(),
+            file_id: last.data.file_id.clone(),
+            position: end,
+            now,
+        }.encode()),
+        _ => None,
+    };
+    SearchResults {
+        results: results.into_iter().map(|(_, result)| result).collect(),
+        total,
+        offset: start,
+        next_cursor,
+        facets: Facets::default(),
+    }
+}
+
+/// Matches the symbol names the `sym:` filters of the query ask for, ignoring case like the
+/// index does. `None` if the query has no such filters.
+fn create_symbol_matcher(parsed_query: &ParsedQuery) -> Result<Option<regex::Regex>, SearchError> {
+    let alternatives = parsed_query.filters.iter()
+        .filter(|filter| filter.kind == FilterKind::Symbol && !filter.negated)
+        .map(|filter| match &filter.value {
+            FilterValue::Regex(pattern) => anchor_path_pattern(pattern, ".*"),
+            FilterValue::Text(name) | FilterValue::Range(_, name) => regex::escape(name),
+        })
+        .collect::<Vec<_>>();
+    if alternatives.is_empty() {
+        return Ok(None);
+    }
+    let pattern = format!("^(?:{})$", alternatives.join("|"));
+    Ok(Some(regex::RegexBuilder::new(&pattern).case_insensitive(true).build()?))
+}
+
+/// The bounds of the values comparing to a span of values, given by its inclusive start and
+/// exclusive end, so `>2026-01-01` starts after that day and `<2026-01-01` ends before it.
+fn range_bounds<T>(comparison: Comparison, from: T, to: T) -> (Bound<T>, Bound<T>) {
+    match comparison {
+        Comparison::Less => (Bound::Unbounded, Bound::Excluded(from)),
+        Comparison::LessOrEqual => (Bound::Unbounded, Bound::Excluded(to)),
+        Comparison::Equal => (Bound::Included(from), Bound::Excluded(to)),
+        Comparison::GreaterOrEqual => (Bound::Included(from), Bound::Unbounded),
+        Comparison::Greater => (Bound::Included(to), Bound::Unbounded),
+    }
+}
+
+/// Turns a user regex into one matching the whole value, as the index requires. `^` and `$`
+/// anchor the pattern, otherwise `padding` is allowed on that side.
+fn anchor_path_pattern(pattern: &str, padding: &str) -> String {
+    let (prefix, pattern) = match pattern.strip_prefix('^') {
+        Some(pattern) => ("", pattern),
+        None => (padding, pattern),
+    };
+    let (pattern, suffix) = match pattern.strip_suffix('$') {
+        Some(pattern) => (pattern, ""),
+        None => (pattern, padding),
+    };
+    format!("{}({}){}", prefix, pattern, suffix)
+}
+
+#[async_trait]
+impl SearchEngine for FileSearchEngine {
+    async fn search(&self, options: SearchOptions) -> Result<SearchResults, SearchError> {
+        log::info!("Executing {:?} search with query: {}", options.mode, options.query);
+        let searcher = self.index.reader()?.searcher();
+        let parsed_query = query_language::parse_query(&options.query)?;
+        let cursor = options.cursor.as_deref().map(|cursor| Cursor::decode(cursor, options.sort_by)).transpose()?;
+        let cursor = cursor.as_ref();
+        match options.mode {
+            SearchMode::Text => self.search_text(&searcher, &parsed_query, &options, cursor),
+            SearchMode::Literal => self.search_literal(&searcher, &parsed_query, &options, cursor),
+            SearchMode::Regex => self.search_regex(&searcher, &parsed_query, &options, cursor),
+        }
+    }
+
+    async fn find_references(&self, options: ReferenceOptions) -> Result<References, SearchError> {
+        log::info!("Finding references of {:?}", options.target);
+        let searcher = self.index.reader()?.searcher();
+        let name = match &options.target {
+            ReferenceTarget::Name(name) => name.clone(),
+            ReferenceTarget::Position { repo, path, line, column } => self.identifier_at_position(&searcher, repo, path, *line, *column)?,
+        };
+
+        let mut files: HashMap<String, FileReferences> = HashMap::new();
+        for file_doc in self.find_definition_documents(&searcher, &name)? {
+            let data = self.schema.create_code_file_dto(&file_doc)?;
+            let definitions = self.schema.extract_symbols(&file_doc).into_iter()
+                .filter(|symbol| symbol.name == name)
+                .collect::<Vec<_>>();
+            if !definitions.is_empty() {
+                files.insert(data.file_id.clone(), FileReferences {
+                    file_id: data.file_id,
+                    repo_name: data.repo_name,
+                    file_path: data.file_path,
+                    definitions,
+                    references: vec![],
+                });
+            }
+        }
+
+        for (doc_address, lines) in self.find_identifier_lines(&searcher, &name)? {
+            let data = self.schema.create_code_file_dto(&searcher.doc(doc_address)?)?;
+            let file_references = files.entry(data.file_id.clone()).or_insert_with(|| FileReferences {
+                file_id: data.file_id.clone(),
+                repo_name: data.repo_name.clone(),
+                file_path: data.file_path.clone(),
+                definitions: vec![],
+                references: vec![],
+            });
+            let content_lines = data.file_content.lines().collect::<Vec<_>>();
+            for line in lines {
+                let is_definition = file_references.definitions.iter().any(|symbol| symbol.line == line);
+                let Some(text) = content_lines.get(line - 1).filter(|_| !is_definition) else {
+                    continue;
+                };
+                for column in references::identifier_columns(text, &name) {
+                    file_references.references.push(Reference {
+                        line,
+                        column,
+                        text: text.to_string(),
+                    });
+                }
+            }
+        }
+
+        let mut files = files.into_values()
+            .filter(|file| !file.definitions.is_empty() || !file.references.is_empty())
+            .collect::<Vec<_>>();
+        files.sort_by(|a, b| a.definitions.is_empty().cmp(&b.definitions.is_empty()).then_with(|| a.file_id.cmp(&b.file_id)));
+        let total_definitions = files.iter().map(|file| file.definitions.len()).sum();
+        let total_references = files.iter().map(|file| file.references.len()).sum();
+        files.truncate(options.limit);
+        Ok(References {
+            name,
+            files,
+            total_definitions,
+            total_references,
+        })
+    }
+
+    async fn code_intel(&self, options: CodeIntelOptions) -> Result<CodeIntel, SearchError> {
+        log::info!("Looking up code intel at {}:{}:{}", options.path, options.line, options.column);
+        let searcher = self.index.reader()?.searcher();
+        let query = self.path_query(&options.repo, &options.path, DocType::Precise);
+        let Some((_, doc_address)) = searcher.search(&query, &TopDocs::with_limit(1))?.into_iter().next() else {
+            return Err(SearchError::not_found(format!("No precise index covers {} in repo {}", options.path, options.repo)));
+        };
+        let document = self.schema.extract_precise_document(&searcher.doc(doc_address)?)?;
+        let Some(occurrence) = document.occurrence_at(options.line, options.column) else {
+            return Err(SearchError::not_found(format!("No symbol at {}:{}:{}", options.path, options.line, options.column)));
+        };
+        let symbol = occurrence.symbol.clone();
+
+        let locations = |documents: Vec<(String, PreciseDocument)>, definitions_only: bool| {
+            let mut locations = documents.into_iter()
+                .flat_map(|(repo_name, document)| {
+                    document.occurrences.into_iter()
+                        .filter(|occurrence| occurrence.symbol == symbol && (occurrence.is_definition || !definitions_only))
+                        .map(move |occurrence| Location {
+                            repo_name: repo_name.clone(),
+                            file_path: document.file_path.clone(),
+                            range: occurrence.range,
+                            is_definition: occurrence.is_definition,
+                        })
+                })
+                .collect::<Vec<_>>();
+            locations.sort_by(|a, b| (&a.repo_name, &a.file_path, a.range).cmp(&(&b.repo_name, &b.file_path, b.range)));
+            locations
+        };
+        let definition_documents = self.find_precise_documents(&searcher, code_schema::CodeSchemaFields::PreciseDefinitions, &symbol)?;
+        let hover = definition_documents.iter()
+            .flat_map(|(_, document)| &document.hovers)
+            .chain(&document.hovers)
+            .find(|hover| hover.symbol == symbol)
+            .map(|hover| hover.text.clone());
+        let definitions = locations(definition_documents, true);
+        let mut references = locations(self.find_precise_documents(&searcher, code_schema::CodeSchemaFields::PreciseSymbols, &symbol)?, false);
+        let total_references = references.len();
+        references.truncate(options.limit);
+        Ok(CodeIntel {
+            symbol,
+            hover,
+            definitions,
+            references,
+            total_references,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::search::facets::FacetCount;
+    use crate::search::Symbol;
+
+    fn create_test_engine(repo_dir: &Path, overrides: &str) -> FileSearchEngine {
+        FileSearchEngine::new(&config::test_config(repo_dir, overrides)).unwrap()
+    }
+
+    /// Commits the files on top of the branch at the given time, creating the branch if needed.
+    fn commit_files(git_repo: &git2::Repository, branch: &str, files: &[(&str, &str)], seconds: i64) -> Oid {
+        let ref_name = format!("refs/heads/{}", branch);
+        let parent = git_repo.find_reference(&ref_name).ok().map(|reference| reference.peel_to_commit().unwrap());
+        let parent_tree = parent.as_ref().map(|parent| parent.tree().unwrap());
+        let mut tree_builder = git_repo.treebuilder(parent_tree.as_ref()).unwrap();
+        for (path, content) in files {
+            tree_builder.insert(path, git_repo.blob(content.as_bytes()).unwrap(), 0o100644).unwrap();
+        }
+        let tree = git_repo.find_tree(tree_builder.write().unwrap()).unwrap();
+        let signature = git2::Signature::new("test", "test@example.com", &git2::Time::new(seconds, 0)).unwrap();
+        let parents = parent.iter().collect::<Vec<_>>();
+        git_repo.commit(Some(&ref_name), &signature, &signature, "commit", &tree, &parents).unwrap()
+    }
+
+    async fn search_file_paths(engine: &FileSearchEngine, query: &str) -> Vec<String> {
+        let results = engine.search(SearchOptions::for_query(query)).await.unwrap().results;
+        results.into_iter().map(|result| result.data.file_path).collect()
+    }
+
+    /// The live and deleted documents in the index, both unchanged by a run that writes nothing.
+    fn document_counts(engine: &FileSearchEngine) -> (u64, u32) {
+        let searcher = engine.index.reader().unwrap().searcher();
+        let deleted_count = searcher.segment_readers().iter().map(|segment_reader| segment_reader.num_deleted_docs()).sum();
+        (searcher.num_docs(), deleted_count)
+    }
+
+    fn set_modified(path: &Path, modified: std::time::SystemTime) {
+        fs::File::options().write(true).open(path).unwrap().set_modified(modified).unwrap();
+    }
+
+    #[tokio::test]
+    async fn unchanged_files_are_skipped_by_modification_time_and_hash() {
+        let repo_dir = tempfile::tempdir().unwrap();
+        let touched_path = repo_dir.path().join("touched.rs");
+        let same_time_path = repo_dir.path().join("same_time.rs");
+        fs::write(&touched_path, "fn kookaburra() {}").unwrap();
+        fs::write(&same_time_path, "fn kookaburra_twin() {}").unwrap();
+
+        let engine = create_test_engine(repo_dir.path(), "");
+        engine.initialize().await.unwrap();
+        let counts = document_counts(&engine);
+
+        // A new modification time with the same content is caught by the hash, and new
+        // content with the old modification time is not read at all
+        set_modified(&touched_path, std::time::SystemTime::now() + std::time::Duration::from_secs(3600));
+        let modified = fs::metadata(&same_time_path).unwrap().modified().unwrap();
+        fs::write(&same_time_path, "fn lyrebird() {}").unwrap();
+        set_modified(&same_time_path, modified);
+        engine.initialize().await.unwrap();
+        assert_eq!(document_counts(&engine), counts);
+        assert_eq!(counts.1, 0);
+        assert!(search_file_paths(&engine, "lyrebird").await.is_empty());
+        assert_eq!(search_file_paths(&engine, "kookaburra_twin").await, vec!["same_time.rs"]);
+    }
+
+    #[tokio::test]
+    async fn modified_file_replaces_its_documents() {
+        let repo_dir = tempfile::tempdir().unwrap();
+        let path = repo_dir.path().join("bird.rs");
+        fs::write(&path, "fn emu() {}").unwrap();
+
+        let engine = create_test_engine(repo_dir.path(), "");
+        engine.initialize().await.unwrap();
+        let (document_count, _) = document_counts(&engine);
+
+        fs::write(&path, "fn cassowary() {}").unwrap();
+        set_modified(&path, std::time::SystemTime::now() + std::time::Duration::from_secs(3600));
+        engine.initialize().await.unwrap();
+        assert_eq!(document_counts(&engine).0, document_count);
+        assert!(search_file_paths(&engine, "emu").await.is_empty());
+        assert_eq!(search_file_paths(&engine, "cassowary").await, vec!["bird.rs"]);
+    }
+
+    #[tokio::test]
+    async fn restart_over_existing_index_changes_nothing() {
+        let repo_dir = tempfile::tempdir().unwrap();
+        fs::write(repo_dir.path().join("main.rs"), "fn bilby() {}").unwrap();
+        fs::write(repo_dir.path().join("lib.rs"), "fn bilby_lib() {}").unwrap();
+        let index_dir = tempfile::tempdir().unwrap();
+        let config = config::test_config(repo_dir.path(), &format!(
+            "indexer: {{use_temporary_index: false, index_path: \"{}\"}}",
+            index_dir.path().display(),
+        ));
+
+        let engine = FileSearchEngine::new(&config).unwrap();
+        engine.initialize().await.unwrap();
+        let counts = document_counts(&engine);
+        drop(engine);
+
+        let engine = FileSearchEngine::new(&config).unwrap();
+        engine.initialize().await.unwrap();
+        assert_eq!(document_counts(&engine), counts);
+        assert_eq!(counts.1, 0);
+        let mut file_paths = search_file_paths(&engine, "bilby").await;
+        file_paths.sort();
+        assert_eq!(file_paths, vec!["lib.rs", "main.rs"]);
+    }
+
+    #[tokio::test]
+    async fn deleted_file_disappears_from_search() {
+        let repo_dir = tempfile::tempdir().unwrap();
+        fs::write(repo_dir.path().join("kept.rs"), "fn quokka() {}").unwrap();
+        fs::write(repo_dir.path().join("deleted.rs"), "fn quokka_helper() { quokka(); }").unwrap();
+
+        let engine = create_test_engine(repo_dir.path(), "");
+        engine.initialize().await.unwrap();
+        let mut file_paths = search_file_paths(&engine, "quokka").await;
+        file_paths.sort();
+        assert_eq!(file_paths, vec!["deleted.rs", "kept.rs"]);
+
+        let deleted_path = repo_dir.path().join("deleted.rs");
+        fs::remove_file(&deleted_path).unwrap();
+        let repo = engine.get_config().repos[0].clone();
+        engine.apply_file_changes(&repo, &[deleted_path]).unwrap();
+        assert_eq!(search_file_paths(&engine, "quokka").await, vec!["kept.rs"]);
+    }
+
+    #[tokio::test]
+    async fn deleted_directory_and_files_removed_between_runs_disappear_from_search() {
+        let repo_dir = tempfile::tempdir().unwrap();
+        fs::create_dir(repo_dir.path().join("module")).unwrap();
+        fs::write(repo_dir.path().join("module/inner.rs"), "fn wombat() {}").unwrap();
+        fs::write(repo_dir.path().join("module.rs"), "mod module; // wombat").unwrap();
+        fs::write(repo_dir.path().join("other.rs"), "fn wombat_other() {}").unwrap();
+
+        let engine = create_test_engine(repo_dir.path(), "");
+        engine.initialize().await.unwrap();
+        assert_eq!(search_file_paths(&engine, "wombat").await.len(), 3);
+
+        let module_dir = repo_dir.path().join("module");
+        fs::remove_dir_all(&module_dir).unwrap();
+        let repo = engine.get_config().repos[0].clone();
+        engine.apply_file_changes(&repo, &[module_dir]).unwrap();
+        let mut file_paths = search_file_paths(&engine, "wombat").await;
+        file_paths.sort();
+        assert_eq!(file_paths, vec!["module.rs", "other.rs"]);
+
+        fs::remove_file(repo_dir.path().join("other.rs")).unwrap();
+        engine.initialize().await.unwrap();
+        assert_eq!(search_file_paths(&engine, "wombat").await, vec!["module.rs"]);
+    }
+
+    #[tokio::test]
+    async fn file_that_becomes_ignored_disappears_from_search() {
+        let repo_dir = tempfile::tempdir().unwrap();
+        fs::create_dir(repo_dir.path().join("generated")).unwrap();
+        fs::write(repo_dir.path().join("generated/api.rs"), "fn numbat() {}").unwrap();
+        fs::write(repo_dir.path().join("main.rs"), "fn main() { numbat(); }").unwrap();
+
+        let engine = create_test_engine(repo_dir.path(), "");
+        engine.initialize().await.unwrap();
+        assert_eq!(search_file_paths(&engine, "numbat").await.len(), 2);
+
+        fs::write(repo_dir.path().join(".gitignore"), "generated/\n").unwrap();
+        let repo = engine.get_config().repos[0].clone();
+        engine.apply_file_changes(&repo, &[repo_dir.path().join("generated")]).unwrap();
+        assert_eq!(search_file_paths(&engine, "numbat").await, vec!["main.rs"]);
+    }
+
+    #[tokio::test]
+    async fn bare_git_repo_is_indexed_at_ref_with_last_commit_time_per_file() {
+        let repo_dir = tempfile::tempdir().unwrap();
+        let git_repo = git2::Repository::init_bare(repo_dir.path()).unwrap();
+        let old_commit = commit_files(&git_repo, "main", &[("old.rs", "fn wombat() {}")], 1_600_000_000);
+        let new_commit = commit_files(&git_repo, "main", &[("new.rs", "fn wombat_new() { wombat(); }")], 1_700_000_000);
+        commit_files(&git_repo, "other", &[("other.rs", "fn wombat_other() {}")], 1_700_000_000);
+
+        let engine = create_test_engine(repo_dir.path(), "repo: {type: git, ref: main}");
+        engine.initialize().await.unwrap();
+        let last_commits = || async {
+            let results = engine.search(SearchOptions { sort_by: SortBy::Path, ..SearchOptions::for_query("wombat") }).await.unwrap().results;
+            results.into_iter()
+                .map(|result| (result.data.file_path, result.data.commit_sha, result.data.file_last_updated.timestamp()))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(last_commits().await, vec![
+            ("new.rs".to_string(), new_commit.to_string(), 1_700_000_000),
+            ("old.rs".to_string(), old_commit.to_string(), 1_600_000_000),
+        ]);
+        assert_eq!(search_file_paths(&engine, "wombat modified:<2021-01-01").await, vec!["old.rs"]);
+
+        // Moving the branch leaves the files it did not change as they were
+        let newer_commit = commit_files(&git_repo, "main", &[("new.rs", "fn wombat_newer() { wombat(); }")], 1_750_000_000);
+        engine.initialize().await.unwrap();
+        assert_eq!(last_commits().await, vec![
+            ("new.rs".to_string(), newer_commit.to_string(), 1_750_000_000),
+            ("old.rs".to_string(), old_commit.to_string(), 1_600_000_000),
+        ]);
+    }
+
+    #[tokio::test]
+    async fn git_branches_share_documents_of_identical_files() {
+        let repo_dir = tempfile::tempdir().unwrap();
+        let git_repo = git2::Repository::init(repo_dir.path()).unwrap();
+        commit_files(&git_repo, "main", &[("shared.rs", "fn quoll() {}"), ("changed.rs", "fn quoll_v1() {}")], 1_600_000_000);
+        let main_commit = git_repo.find_reference("refs/heads/main").unwrap().peel_to_commit().unwrap();
+        git_repo.branch("feature", &main_commit, false).unwrap();
+        commit_files(&git_repo, "feature", &[("changed.rs", "fn quoll_v2() {}"), ("feature.rs", "fn quoll_feature() {}")], 1_700_000_000);
+
+        let engine = create_test_engine(repo_dir.path(), "repo: {type: git, branches: [main, feature]}");
+        engine.initialize().await.unwrap();
+        let results = engine.search(SearchOptions { sort_by: SortBy::Path, ..SearchOptions::for_query("quoll") }).await.unwrap();
+        let mut files = results.results.iter()
+            .map(|result| (result.data.file_path.as_str(), result.data.branch.join(",")))
+            .collect::<Vec<_>>();
+        files.sort();
+        assert_eq!(results.total, 4);
+        assert_eq!(files, vec![
+            ("changed.rs", "feature".to_string()),
+            ("changed.rs", "main".to_string()),
+            ("feature.rs", "feature".to_string()),
+            ("shared.rs", "main,feature".to_string()),
+        ]);
+        let shared_file_id = &results.results.last().unwrap().data.file_id;
+        let blob_id = git_repo.revparse_single("main:shared.rs").unwrap().id().to_string();
+        assert_eq!(shared_file_id, &CodeFileSchema::create_git_file_id("test", "shared.rs", &blob_id));
+
+        let mut file_paths = search_file_paths(&engine, "quoll branch:main").await;
+        file_paths.sort();
+        assert_eq!(file_paths, vec!["changed.rs", "shared.rs"]);
+        let mut file_paths = search_file_paths(&engine, "quoll branch:feature -file:shared.rs").await;
+        file_paths.sort();
+        assert_eq!(file_paths, vec!["changed.rs", "feature.rs"]);
+    }
+
+    #[tokio::test]
+    async fn binary_and_unreadable_files_are_reported_as_skipped() {
+        let repo_dir = tempfile::tempdir().unwrap();
+        fs::write(repo_dir.path().join("binary.rs"), b"fn\x00\x01\x02").unwrap();
+        fs::write(repo_dir.path().join("latin1.rs"), b"// gr\xFC\xDFe\nfn galah() {}").unwrap();
+
+        let engine = create_test_engine(repo_dir.path(), "");
+        engine.initialize().await.unwrap();
+        let skipped_files = engine.get_skipped_files("test").into_iter()
+            .map(|skipped_file| (skipped_file.file_id, skipped_file.reason))
+            .collect::<Vec<_>>();
+        assert_eq!(skipped_files, vec![("test/binary.rs".to_string(), SkipReason::Binary)]);
+        assert_eq!(search_file_paths(&engine, "galah").await, vec!["latin1.rs"]);
+    }
+
+    #[tokio::test]
+    async fn changed_files_update_the_skipped_files() {
+        let repo_dir = tempfile::tempdir().unwrap();
+        let paths = [repo_dir.path().join("data.rs")];
+        let path = &paths[0];
+        fs::write(path, "fn galah() {}").unwrap();
+
+        let engine = create_test_engine(repo_dir.path(), "");
+        engine.initialize().await.unwrap();
+        let repo = engine.get_config().repos[0].clone();
+        fs::write(path, b"fn\x00\x01\x02").unwrap();
+        engine.apply_file_changes(&repo, &paths).unwrap();
+        let skipped_files = engine.get_skipped_files("test").into_iter()
+            .map(|skipped_file| (skipped_file.file_id, skipped_file.reason))
+            .collect::<Vec<_>>();
+        assert_eq!(skipped_files, vec![("test/data.rs".to_string(), SkipReason::Binary)]);
+        assert!(search_file_paths(&engine, "galah").await.is_empty());
+
+        fs::write(path, "fn galah() {}").unwrap();
+        engine.apply_file_changes(&repo, &paths).unwrap();
+        assert!(engine.get_skipped_files("test").is_empty());
+        assert_eq!(search_file_paths(&engine, "galah").await, vec!["data.rs"]);
+    }
+
+    /// An engine indexing a repo with a small `small.rs` and a 195 byte `large.rs` under a
+    /// 64 byte size limit. The end of the large file is past the limit.
+    async fn index_large_file(repo_dir: &Path, large_files: &str) -> FileSearchEngine {
+        fs::write(repo_dir.join("small.rs"), "fn dugong_small() {}").unwrap();
+        fs::write(repo_dir.join("large.rs"), format!("fn dugong() {{}}\n{}fn manatee() {{}}", "// padding\n".repeat(15))).unwrap();
+        let engine = create_test_engine(repo_dir, &format!("indexer: {{max_file_size: 64, large_files: {}}}", large_files));
+        engine.initialize().await.unwrap();
+        engine
+    }
+
+    async fn find_large_file(engine: &FileSearchEngine, query: &str) -> Option<CodeFileDto> {
+        let results = engine.search(SearchOptions { include_content: true, ..SearchOptions::for_query(query) }).await.unwrap().results;
+        results.into_iter().map(|result| result.data).find(|data| data.file_path == "large.rs")
+    }
+
+    #[tokio::test]
+    async fn large_files_are_skipped() {
+        let repo_dir = tempfile::tempdir().unwrap();
+        let engine = index_large_file(repo_dir.path(), "skip").await;
+        assert_eq!(search_file_paths(&engine, "dugong").await, vec!["small.rs"]);
+        assert!(find_large_file(&engine, "large").await.is_none());
+        let skipped_files = engine.get_skipped_files("test").into_iter()
+            .map(|skipped_file| (skipped_file.file_id, skipped_file.reason))
+            .collect::<Vec<_>>();
+        assert_eq!(skipped_files, vec![("test/large.rs".to_string(), SkipReason::TooLarge(195))]);
+    }
+
+    #[tokio::test]
+    async fn large_files_are_truncated() {
+        let repo_dir = tempfile::tempdir().unwrap();
+        let engine = index_large_file(repo_dir.path(), "truncate").await;
+        let data = find_large_file(&engine, "dugong").await.unwrap();
+        assert_eq!(data.file_content_policy, FileContentPolicy::Truncated);
+        assert_eq!(data.file_size, 195);
+        assert_eq!(data.file_content.len(), 64);
+        assert!(data.file_content.starts_with("fn dugong() {}"));
+        assert!(find_large_file(&engine, "manatee").await.is_none());
+    }
+
+    #[tokio::test]
+    async fn large_files_keep_only_metadata() {
+        let repo_dir = tempfile::tempdir().unwrap();
+        let engine = index_large_file(repo_dir.path(), "metadata_only").await;
+        assert_eq!(search_file_paths(&engine, "dugong").await, vec!["small.rs"]);
+        let data = find_large_file(&engine, "large").await.unwrap();
+        assert_eq!(data.file_content_policy, FileContentPolicy::MetadataOnly);
+        assert_eq!(data.file_size, 195);
+        assert!(data.file_content.is_empty());
+    }
+
+    #[tokio::test]
+    async fn match_in_long_file_reports_chunk_line_range() {
+        let repo_dir = tempfile::tempdir().unwrap();
+        let mut lines = vec!["fn filler() {}"; 120];
+        lines[99] = "fn platypus() {}";
+        fs::write(repo_dir.path().join("long.rs"), lines.join("\n")).unwrap();
+
+        let engine = create_test_engine(repo_dir.path(), "");
+        engine.initialize().await.unwrap();
+        let results = engine.search(SearchOptions { context_lines: 2, ..SearchOptions::for_query("platypus") }).await.unwrap().results;
+
+        assert_eq!(results.len(), 1);
+        let mut line_ranges = results[0].chunks.iter()
+            .map(|chunk| (chunk.start_line, chunk.end_line))
+            .collect::<Vec<_>>();
+        line_ranges.sort();
+        assert_eq!(line_ranges, vec![(81, 120)]);
+
+        assert!(results[0].data.file_content.is_empty());
+        let snippet = &results[0].snippets[0];
+        assert_eq!((snippet.start_line, snippet.end_line), (98, 102));
+        let highlighted_lines = snippet.lines.iter()
+            .filter(|line| !line.highlights.is_empty())
+            .map(|line| (line.line_number, line.highlights[0].char_start, line.highlights[0].char_end))
+            .collect::<Vec<_>>();
+        assert_eq!(highlighted_lines, vec![(100, 3, 11)]);
+    }
+
+    #[tokio::test]
+    async fn excluded_terms_apply_to_whole_files_not_chunks() {
+        let repo_dir = tempfile::tempdir().unwrap();
+        let mut lines = vec!["fn filler() {}"; 120];
+        lines[10] = "fn apple() {}";
+        lines[100] = "fn banana() {}";
+        fs::write(repo_dir.path().join("long.rs"), lines.join("\n")).unwrap();
+        fs::write(repo_dir.path().join("short.rs"), "fn apple() {}").unwrap();
+
+        let engine = create_test_engine(repo_dir.path(), "");
+        engine.initialize().await.unwrap();
+        for sort_by in [SortBy::Score, SortBy::Path] {
+            let results = engine.search(SearchOptions { sort_by, ..SearchOptions::for_query("apple -banana") }).await.unwrap();
+            assert_eq!(results.total, 1);
+            let file_paths = results.results.iter().map(|result| result.data.file_path.as_str()).collect::<Vec<_>>();
+            assert_eq!(file_paths, vec!["short.rs"]);
+        }
+        let results = engine.search(SearchOptions::for_query("apple AND banana")).await.unwrap();
+        assert_eq!(results.total, 1);
+        assert_eq!(results.results[0].data.file_path, "long.rs");
+    }
+
+    #[tokio::test]
+    async fn identifier_parts_find_camel_and_snake_case_names() {
+        let repo_dir = tempfile::tempdir().unwrap();
+        fs::write(repo_dir.path().join("camel.rs"), "fn parseQuery() {}").unwrap();
+        fs::write(repo_dir.path().join("snake.rs"), "fn query_parser() {}").unwrap();
+        fs::write(repo_dir.path().join("path.rs"), "use std::fs;").unwrap();
+
+        let engine = create_test_engine(repo_dir.path(), "");
+        engine.initialize().await.unwrap();
+        let mut file_paths = search_file_paths(&engine, "query").await;
+        file_paths.sort();
+        assert_eq!(file_paths, vec!["camel.rs", "snake.rs"]);
+        assert_eq!(search_file_paths(&engine, "parseQuery").await, vec!["camel.rs"]);
+        assert_eq!(search_file_paths(&engine, "\"std::fs\"").await, vec!["path.rs"]);
+    }
+
+    #[tokio::test]
+    async fn literal_search_finds_exact_substrings() {
+        let repo_dir = tempfile::tempdir().unwrap();
+        fs::write(repo_dir.path().join("exact.rs"), "fn a() {\n    value.unwrap().as_text();\n}").unwrap();
+        fs::write(repo_dir.path().join("words.rs"), "fn b() { value.unwrap(); as_text(); }").unwrap();
+
+        let engine = create_test_engine(repo_dir.path(), "");
+        engine.initialize().await.unwrap();
+        let results = engine.search(SearchOptions { mode: SearchMode::Literal, ..SearchOptions::for_query(".unwrap().as_text()") }).await.unwrap().results;
+
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].data.file_path, "exact.rs");
+        let line = &results[0].snippets[0].lines[0];
+        assert_eq!(line.line_number, 2);
+        assert_eq!((line.highlights[0].byte_start, line.highlights[0].byte_end), (9, 28));
+    }
+
+    #[tokio::test]
+    async fn regex_search_returns_matching_lines() {
+        let repo_dir = tempfile::tempdir().unwrap();
+        fs::write(repo_dir.path().join("users.rs"), "fn get_user_by_id() {}\nfn get_group_by_name() {}\nfn other() {}").unwrap();
+        fs::write(repo_dir.path().join("other.rs"), "fn get_user() {}").unwrap();
+
+        let engine = create_test_engine(repo_dir.path(), "");
+        engine.initialize().await.unwrap();
+        let results = engine.search(SearchOptions { mode: SearchMode::Regex, ..SearchOptions::for_query(r"^fn get_(user|group)_by_\w+") }).await.unwrap().results;
+
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].data.file_path, "users.rs");
+        let line_numbers = results[0].snippets.iter()
+            .flat_map(|snippet| snippet.lines.iter().map(|line| line.line_number))
+            .collect::<Vec<_>>();
+        assert_eq!(line_numbers, vec![1, 2]);
+    }
+
+    #[tokio::test]
+    async fn malformed_queries_are_invalid_query_errors() {
+        let repo_dir = tempfile::tempdir().unwrap();
+        fs::write(repo_dir.path().join("users.rs"), "fn get_user() {}").unwrap();
+
+        let engine = create_test_engine(repo_dir.path(), "");
+        engine.initialize().await.unwrap();
+        let search_error = |query: &'static str, mode: SearchMode| {
+            let engine = &engine;
+            async move {
+                engine.search(SearchOptions { mode, ..SearchOptions::for_query(query) }).await.unwrap_err()
+            }
+        };
+
+        let err = search_error("repo:test get_(user", SearchMode::Regex).await;
+        assert!(matches!(err, SearchError::InvalidQuery { .. }));
+        assert_eq!(err.position(), Some(14));
+        let err = search_error("file:get_(user get_(user", SearchMode::Regex).await;
+        assert_eq!(err.position(), Some(19));
+        let err = search_error("repo:test get_user AND (profile", SearchMode::Text).await;
+        assert!(matches!(err, SearchError::InvalidQuery { .. }));
+        assert_eq!(err.position(), Some(31));
+        let err = search_error("repo:test .*", SearchMode::Regex).await;
+        assert_eq!(err.position(), Some(10));
+        let err = search_error("fn", SearchMode::Literal).await;
+        assert!(matches!(err, SearchError::InvalidQuery { position: Some(0), .. }));
+        let err = search_error("get_user size:>lots", SearchMode::Text).await;
+        assert!(matches!(err, SearchError::InvalidQuery { position: None, .. }));
+    }
+
+    #[tokio::test]
+    async fn filters_restrict_and_exclude_results() {
+        let repo_dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(repo_dir.path().join("src/api")).unwrap();
+        fs::write(repo_dir.path().join("src/api/handler.rs"), "fn echidna() {}").unwrap();
+        fs::write(repo_dir.path().join("src/api/handler_test.rs"), "fn echidna_test() { echidna(); }").unwrap();
+        fs::write(repo_dir.path().join("src/main.rs"), "fn main() { echidna(); }").unwrap();
+
+        let engine = create_test_engine(repo_dir.path(), "");
+        engine.initialize().await.unwrap();
+        assert_eq!(search_file_paths(&engine, r"echidna path:SRC/API -path:/_test\.rs$/").await, vec!["src/api/handler.rs"]);
+        assert!(search_file_paths(&engine, "echidna path:SRC/API case:yes").await.is_empty());
+        assert_eq!(search_file_paths(&engine, "lang:python").await, Vec::<String>::new());
+        let mut file_paths = search_file_paths(&engine, "repo:test lang:rust ext:rs file:/^(main|handler)/").await;
+        file_paths.sort();
+        assert_eq!(file_paths, vec!["src/api/handler.rs", "src/api/handler_test.rs", "src/main.rs"]);
+        assert!(search_file_paths(&engine, "echidna repo:other").await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn cursor_pages_through_all_results() {
+        let repo_dir = tempfile::tempdir().unwrap();
+        for index in 0..5 {
+            fs::write(repo_dir.path().join(format!("file{}.rs", index)), "fn kiwi() {}").unwrap();
+        }
+
+        let engine = create_test_engine(repo_dir.path(), "");
+        engine.initialize().await.unwrap();
+        let mut file_paths = Vec::new();
+        let mut cursor = None;
+        loop {
+            let page = engine.search(SearchOptions { limit: 2, cursor, ..SearchOptions::for_query("kiwi") }).await.unwrap();
+            assert_eq!(page.total, 5);
+            assert_eq!(page.offset, file_paths.len());
+            file_paths.extend(page.results.into_iter().map(|result| result.data.file_path));
+            cursor = page.next_cursor;
+            if cursor.is_none() {
+                break;
+            }
+        }
+        assert_eq!(file_paths, vec!["file0.rs", "file1.rs", "file2.rs", "file3.rs", "file4.rs"]);
+    }
+
+    #[tokio::test]
+    async fn score_pages_are_full_when_files_have_many_matching_chunks() {
+        let repo_dir = tempfile::tempdir().unwrap();
+        for index in 0..6 {
+            fs::write(repo_dir.path().join(format!("file{}.rs", index)), "fn kiwi() {}\n".repeat(40)).unwrap();
+        }
+
+        let engine = create_test_engine(repo_dir.path(), "indexer: {chunking: {lines: 2, overlap: 1}}");
+        engine.initialize().await.unwrap();
+        let mut file_paths = Vec::new();
+        let mut cursor = None;
+        loop {
+            let page = engine.search(SearchOptions { limit: 4, cursor, ..SearchOptions::for_query("kiwi") }).await.unwrap();
+            assert_eq!(page.total, 6);
+            assert!(page.results.iter().all(|result| !result.chunks.is_empty()));
+            file_paths.extend(page.results.into_iter().map(|result| result.data.file_path));
+            cursor = page.next_cursor;
+            if cursor.is_none() {
+                break;
+            }
+        }
+        file_paths.sort();
+        assert_eq!(file_paths, (0..6).map(|index| format!("file{}.rs", index)).collect::<Vec<_>>());
+    }
+
+    #[tokio::test]
+    async fn later_pages_rank_recency_against_the_time_of_the_cursor() {
+        let repo_dir = tempfile::tempdir().unwrap();
+        fs::write(repo_dir.path().join("a.rs"), "fn kiwi() {}").unwrap();
+        fs::write(repo_dir.path().join("b.rs"), "fn kiwi() {}").unwrap();
+
+        let engine = create_test_engine(repo_dir.path(), "");
+        engine.initialize().await.unwrap();
+        for mode in [SearchMode::Text, SearchMode::Literal] {
+            let options = || SearchOptions { mode, limit: 1, explain: true, ..SearchOptions::for_query("kiwi") };
+            let first_page = engine.search(options()).await.unwrap();
+            assert!(first_page.results[0].explanation.as_ref().unwrap().recency > 1.1);
+
+            let mut cursor = Cursor::decode(&first_page.next_cursor.unwrap(), SortBy::Score).unwrap();
+            cursor.now += 10 * 365 * 24 * 3600 * 1000;
+            let second_page = engine.search(SearchOptions { cursor: Some(cursor.encode()), ..options() }).await.unwrap();
+            assert_eq!(second_page.results.len(), 1);
+            assert!(second_page.results[0].explanation.as_ref().unwrap().recency < 1.01);
+        }
+    }
+
+    #[tokio::test]
+    async fn facets_count_all_matching_files() {
+        let repo_dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(repo_dir.path().join("src")).unwrap();
+        fs::write(repo_dir.path().join("src/lib.rs"), "fn quokka() {}").unwrap();
+        fs::write(repo_dir.path().join("src/main.rs"), "fn main() { quokka(); }").unwrap();
+        fs::write(repo_dir.path().join("build.rs"), "fn build() { quokka(); }").unwrap();
+
+        let engine = create_test_engine(repo_dir.path(), "");
+        engine.initialize().await.unwrap();
+        for mode in [SearchMode::Text, SearchMode::Literal] {
+            let results = engine.search(SearchOptions { mode, limit: 1, ..SearchOptions::for_query("quokka") }).await.unwrap();
+            let counts = |facet: &[FacetCount]| facet.iter()
+                .map(|count| (count.value.clone(), count.count))
+                .collect::<Vec<_>>();
+            assert_eq!(counts(&results.facets.repo_name), vec![("test".to_string(), 3)]);
+            assert_eq!(counts(&results.facets.file_ext), vec![("rs".to_string(), 3)]);
+            assert_eq!(counts(&results.facets.file_language), vec![("Rust".to_string(), 3)]);
+            assert_eq!(counts(&results.facets.top_level_dir), vec![("src".to_string(), 2), ("/".to_string(), 1)]);
+        }
+    }
+
+    #[tokio::test]
+    async fn sorts_and_filters_by_size_and_modification_time() {
+        let repo_dir = tempfile::tempdir().unwrap();
+        fs::write(repo_dir.path().join("b.rs"), "fn wombat() {}").unwrap();
+        fs::write(repo_dir.path().join("c.rs"), format!("fn wombat() {{}}\n{}", "// padding\n".repeat(200))).unwrap();
+        fs::write(repo_dir.path().join("a.rs"), "fn wombat() { wombat(); }").unwrap();
+        let old_time = std::time::SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_600_000_000);
+        fs::File::options().write(true).open(repo_dir.path().join("a.rs")).unwrap().set_modified(old_time).unwrap();
+
+        let engine = create_test_engine(repo_dir.path(), "");
+        engine.initialize().await.unwrap();
+        let search_sorted = |query: &'static str, mode: SearchMode, sort_by: SortBy| {
+            let engine = &engine;
+            async move {
+                let results = engine.search(SearchOptions { mode, sort_by, ..SearchOptions::for_query(query) }).await.unwrap().results;
+                results.into_iter().map(|result| result.data.file_path).collect::<Vec<_>>()
+            }
+        };
+        for mode in [SearchMode::Text, SearchMode::Literal] {
+            assert_eq!(search_sorted("wombat", mode, SortBy::Path).await, vec!["a.rs", "b.rs", "c.rs"]);
+            assert_eq!(search_sorted("wombat", mode, SortBy::Size).await, vec!["c.rs", "a.rs", "b.rs"]);
+            assert_eq!(search_sorted("wombat", mode, SortBy::Modified).await.last().unwrap(), "a.rs");
+        }
+        assert_eq!(search_sorted("wombat size:>1kb", SearchMode::Text, SortBy::Score).await, vec!["c.rs"]);
+        assert_eq!(search_sorted("wombat size:<=14", SearchMode::Literal, SortBy::Score).await, vec!["b.rs"]);
+        assert_eq!(search_sorted("wombat size:<=100000000000gb", SearchMode::Text, SortBy::Path).await, vec!["a.rs", "b.rs", "c.rs"]);
+        assert!(search_sorted("wombat size:>100000000000gb", SearchMode::Text, SortBy::Path).await.is_empty());
+        assert_eq!(search_sorted("wombat modified:<2021-01-01", SearchMode::Text, SortBy::Score).await, vec!["a.rs"]);
+        assert_eq!(search_sorted("wombat modified:2020-09-13 -size:>1kb", SearchMode::Text, SortBy::Path).await, vec!["a.rs"]);
+    }
+
+    #[tokio::test]
+    async fn file_name_matches_outrank_demoted_fixtures() {
+        let repo_dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(repo_dir.path().join("src")).unwrap();
+        fs::create_dir_all(repo_dir.path().join("tests")).unwrap();
+        fs::write(repo_dir.path().join("src/tokenizer.rs"), "pub struct Tokenizer;").unwrap();
+        fs::write(repo_dir.path().join("tests/fixtures.rs"), "// tokenizer\n".repeat(50)).unwrap();
+
+        let engine = create_test_engine(repo_dir.path(), "");
+        engine.initialize().await.unwrap();
+        let results = engine.search(SearchOptions { explain: true, ..SearchOptions::for_query("tokenizer") }).await.unwrap().results;
+        let file_paths = results.iter().map(|result| result.data.file_path.as_str()).collect::<Vec<_>>();
+        assert_eq!(file_paths, vec!["src/tokenizer.rs", "tests/fixtures.rs"]);
+        let explanation = results[1].explanation.as_ref().unwrap();
+        assert_eq!(explanation.demotion, 0.5);
+        assert!(explanation.details.is_some());
+        assert!((explanation.score - results[1]._score).abs() < 1e-4);
+    }
+
+    #[tokio::test]
+    async fn symbol_filter_finds_definitions_only() {
+        let repo_dir = tempfile::tempdir().unwrap();
+        fs::write(repo_dir.path().join("engine.rs"), "use std::fs;\n\npub struct SearchIndex {\n    path: String,\n}").unwrap();
+        fs::write(repo_dir.path().join("main.rs"), "fn main() {\n    let index = SearchIndex::open();\n}").unwrap();
+
+        let engine = create_test_engine(repo_dir.path(), "");
+        engine.initialize().await.unwrap();
+        assert_eq!(search_file_paths(&engine, "SearchIndex").await.len(), 2);
+        for query in ["sym:searchindex", "sym:/search.*/"] {
+            let results = engine.search(SearchOptions::for_query(query)).await.unwrap().results;
+            assert_eq!(results.len(), 1);
+            assert_eq!(results[0].data.file_path, "engine.rs");
+            assert_eq!(results[0].symbols, vec![Symbol {
+                name: "SearchIndex".to_string(),
+                kind: symbols::SymbolKind::Struct,
+                line: 3,
+            }]);
+            assert_eq!(results[0].snippets[0].start_line, 3);
+        }
+        assert_eq!(search_file_paths(&engine, "main -sym:main").await, Vec::<String>::new());
+    }
+
+    #[tokio::test]
+    async fn finds_references_by_name_and_position() {
+        let repo_dir = tempfile::tempdir().unwrap();
+        fs::write(repo_dir.path().join("dto.rs"), "pub fn create_dto(id: u32) -> Dto {\n    Dto { id }\n}").unwrap();
+        fs::write(repo_dir.path().join("main.rs"), "fn main() {\n    let dto = create_dto(1);\n    let dtos = vec![create_dto(2), create_dto(3)];\n}").unwrap();
+
+        let engine = create_test_engine(repo_dir.path(), "");
+        engine.initialize().await.unwrap();
+        let targets = [
+            ReferenceTarget::Name("create_dto".to_string()),
+            ReferenceTarget::Position {
+                repo: "test".to_string(),
+                path: "main.rs".to_string(),
+                line: 2,
+                column: 17,
+            },
+        ];
+        for target in targets {
+            let references = engine.find_references(ReferenceOptions { target, limit: 10 }).await.unwrap();
+            assert_eq!(references.name, "create_dto");
+            assert_eq!(references.total_definitions, 1);
+            assert_eq!(references.total_references, 3);
+            assert_eq!(references.files[0].file_path, "dto.rs");
+            assert_eq!(references.files[0].definitions[0].line, 1);
+            assert!(references.files[0].references.is_empty());
+            let positions = references.files[1].references.iter()
+                .map(|reference| (reference.line, reference.column))
+                .collect::<Vec<_>>();
+            assert_eq!(positions, vec![(2, 15), (3, 21), (3, 36)]);
+        }
+    }
+
+    #[tokio::test]
+    async fn precise_index_serves_definitions_references_and_hover() {
+        use prost::Message;
+        use crate::search::code_intel::{ScipDocument, ScipIndex, ScipOccurrence, ScipSymbolInformation};
+
+        let repo_dir = tempfile::tempdir().unwrap();
+        fs::write(repo_dir.path().join("lib.rs"), "pub fn greet() {}").unwrap();
+        fs::write(repo_dir.path().join("main.rs"), "fn main() {\n    greet();\n    greet();\n}").unwrap();
+        let symbol = "rust-analyzer cargo test 0.1.0 greet().";
+        let occurrence = |range: Vec<i32>, symbol: &str, symbol_roles: i32| ScipOccurrence { range, symbol: symbol.to_string(), symbol_roles };
+        let index = ScipIndex {
+            documents: vec![
+                ScipDocument {
+                    relative_path: "lib.rs".to_string(),
+                    occurrences: vec![occurrence(vec![0, 7, 12], symbol, 1)],
+                    symbols: vec![ScipSymbolInformation {
+                        symbol: symbol.to_string(),
+                        documentation: vec!["```rust\npub fn greet()\n```".to_string()],
+                    }],
+                },
+                ScipDocument {
+                    relative_path: "main.rs".to_string(),
+                    occurrences: vec![
+                        occurrence(vec![1, 4, 9], symbol, 0),
+                        occurrence(vec![2, 4, 9], symbol, 0),
+                        occurrence(vec![0, 3, 7], "local 0", 1),
+                    ],
+                    symbols: vec![],
+                },
+            ],
+            external_symbols: vec![],
+        };
+        fs::write(repo_dir.path().join("index.scip"), index.encode_to_vec()).unwrap();
+
+        let engine = create_test_engine(repo_dir.path(), "repo: {precise_index: {format: scip, path: index.scip}}");
+        // The second run finds the dump unchanged and keeps the imported documents
+        engine.initialize().await.unwrap();
+        engine.initialize().await.unwrap();
+
+        let code_intel = |line: usize, column: usize| engine.code_intel(CodeIntelOptions {
+            repo: "test".to_string(),
+            path: "main.rs".to_string(),
+            line,
+            column,
+            limit: 10,
+        });
+        let result = code_intel(3, 7).await.unwrap();
+        assert_eq!(result.symbol, symbol);
+        assert_eq!(result.hover.as_deref(), Some("```rust\npub fn greet()\n```"));
+        assert_eq!(result.definitions.len(), 1);
+        assert_eq!(result.definitions[0].file_path, "lib.rs");
+        assert_eq!(result.definitions[0].range, code_intel::SourceRange { start_line: 1, start_column: 8, end_line: 1, end_column: 13 });
+        assert_eq!(result.total_references, 3);
+        let reference_lines = result.references.iter()
+            .map(|location| (location.file_path.as_str(), location.range.start_line))
+            .collect::<Vec<_>>();
+        assert_eq!(reference_lines, vec![("lib.rs", 1), ("main.rs", 2), ("main.rs", 3)]);
+
+        let local = code_intel(1, 5).await.unwrap();
+        assert_eq!(local.symbol, "local test/main.rs#0");
+        assert_eq!(local.hover, None);
+        assert!(code_intel(1, 1).await.is_err());
+        assert_eq!(search_file_paths(&engine, "greet").await.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn index_built_with_older_schema_is_rebuilt() {
+        let repo_dir = tempfile::tempdir().unwrap();
+        fs::write(repo_dir.path().join("main.rs"), "fn wombat() {}").unwrap();
+        let index_dir = tempfile::tempdir().unwrap();
+        let mut schema_builder = tantivy::schema::Schema::builder();
+        schema_builder.add_text_field("file_size", tantivy::schema::STRING | tantivy::schema::STORED);
+        tantivy::Index::create_in_dir(index_dir.path(), schema_builder.build()).unwrap();
+
+        let config = config::test_config(repo_dir.path(), &format!(
+            "indexer: {{use_temporary_index: false, index_path: \"{}\", incremental_reindex: false}}",
+            index_dir.path().display(),
+        ));
+        let engine = FileSearchEngine::new(&config).unwrap();
+        assert!(engine.index_rebuilt);
+        engine.initialize().await.unwrap();
+        assert_eq!(search_file_paths(&engine, "wombat").await, vec!["main.rs"]);
+        drop(engine);
+
+        let version = fs::read_to_string(index_dir.path().join(SCHEMA_VERSION_FILE)).unwrap();
+        assert_eq!(version, code_schema::SCHEMA_VERSION.to_string());
+        let engine = FileSearchEngine::new(&config).unwrap();
+        assert!(!engine.index_rebuilt);
+        assert_eq!(search_file_paths(&engine, "wombat").await, vec!["main.rs"]);
     }
 }
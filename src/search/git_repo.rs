@@ -0,0 +1,145 @@
+use std::collections::{HashMap, HashSet};
+
+use git2::{DiffOptions, ObjectType, Oid, Repository, TreeWalkMode, TreeWalkResult};
+
+/// A blob in the committed tree of a git repo, with its path relative to the repo root.
+pub(crate) struct GitTreeFile {
+    pub(crate) path: String,
+    pub(crate) blob_id: Oid,
+}
+
+/// The files of a git repo as they are committed at the resolved ref.
+pub(crate) struct GitTreeSnapshot {
+    pub(crate) commit_sha: String,
+    pub(crate) commit_time: chrono::DateTime<chrono::Utc>,
+    pub(crate) files: Vec<GitTreeFile>,
+}
+
+const GIT_FILEMODE_LINK: i32 = 0o120000;
+
+/// Opens a working copy or bare repository, the path may point at either.
+pub(crate) fn open_repository(repo_path: &str) -> Result<Repository, git2::Error> {
+    Repository::open(repo_path)
+}
+
+/// Resolves `git_ref` to a commit and lists the blobs of its tree. Directories and files for
+/// which `skip_path` returns true are not descended into or listed, it is given the path and
+/// whether the path is a directory.
+pub(crate) fn read_tree_snapshot(
+    git_repo: &Repository,
+    git_ref: &str,
+    skip_path: impl Fn(&str, bool) -> bool,
+) -> Result<GitTreeSnapshot, git2::Error> {
+    let commit = git_repo.revparse_single(git_ref)?.peel_to_commit()?;
+    let commit_time = to_datetime(commit.time());
+
+    let mut files = Vec::new();
+    commit.tree()?.walk(TreeWalkMode::PreOrder, |root, entry| {
+        let name = match entry.name() {
+            Some(name) => name,
+            None => return TreeWalkResult::Skip,
+        };
+        let path = format!("{}{}", root, name);
+        if skip_path(&path, entry.kind() == Some(ObjectType::Tree)) {
+            return TreeWalkResult::Skip;
+        }
+        if entry.kind() == Some(ObjectType::Blob) && entry.filemode() != GIT_FILEMODE_LINK {
+            files.push(GitTreeFile {
+                path,
+                blob_id: entry.id(),
+            });
+        }
+        TreeWalkResult::Ok
+    })?;
+
+    Ok(GitTreeSnapshot {
+        commit_sha: commit.id().to_string(),
+        commit_time,
+        files,
+    })
+}
+
+/// The last commit that changed a file.
+#[derive(Debug, Clone)]
+pub(crate) struct LastCommit {
+    pub(crate) sha: String,
+    pub(crate) time: chrono::DateTime<chrono::Utc>,
+}
+
+/// The last commits of files, by path.
+pub(crate) type LastCommits = HashMap<String, LastCommit>;
+
+/// Adds the last commit up to `commit_sha` that changed each of the paths to `last_commits`,
+/// following first parents like `git log --first-parent`. Paths already in `last_commits`
+/// are not looked up again, as they never change for a given commit, and history is only
+/// walked until every path is found, diffing just the paths still missing.
+pub(crate) fn read_last_commits(
+    git_repo: &Repository,
+    commit_sha: &str,
+    paths: &HashSet<&str>,
+    last_commits: &mut LastCommits,
+) -> Result<(), git2::Error> {
+    let mut remaining_paths = paths.iter()
+        .filter(|path| !last_commits.contains_key(**path))
+        .copied()
+        .collect::<HashSet<_>>();
+    if remaining_paths.is_empty() {
+        return Ok(());
+    }
+    let mut diff_options = path_diff_options(&remaining_paths);
+    let mut revwalk = git_repo.revwalk()?;
+    revwalk.push(Oid::from_str(commit_sha)?)?;
+    revwalk.simplify_first_parent()?;
+    for commit_id in revwalk {
+        let commit = git_repo.find_commit(commit_id?)?;
+        let parent_tree = match commit.parent(0) {
+            Ok(parent) => Some(parent.tree()?),
+            Err(_) => None,
+        };
+        let diff = git_repo.diff_tree_to_tree(parent_tree.as_ref(), Some(&commit.tree()?), Some(&mut diff_options))?;
+        let mut found_path = false;
+        for delta in diff.deltas() {
+            let Some(path) = delta.new_file().path().and_then(|path| path.to_str()) else {
+                continue;
+            };
+            if remaining_paths.remove(path) {
+                found_path = true;
+                last_commits.insert(path.to_string(), LastCommit {
+                    sha: commit.id().to_string(),
+                    time: to_datetime(commit.time()),
+                });
+            }
+        }
+        if remaining_paths.is_empty() {
+            break;
+        }
+        if found_path {
+            diff_options = path_diff_options(&remaining_paths);
+        }
+    }
+    Ok(())
+}
+
+/// Diff options limited to exactly the given paths.
+fn path_diff_options(paths: &HashSet<&str>) -> DiffOptions {
+    let mut diff_options = DiffOptions::new();
+    diff_options.disable_pathspec_match(true);
+    for path in paths {
+        diff_options.pathspec(*path);
+    }
+    diff_options
+}
+
+fn to_datetime(time: git2::Time) -> chrono::DateTime<chrono::Utc> {
+    chrono::DateTime::from_timestamp(time.seconds(), 0).unwrap_or_else(chrono::Utc::now)
+}
+
+/// Looks up the size of a blob without loading its content.
+pub(crate) fn read_blob_size(git_repo: &Repository, blob_id: Oid) -> Result<u64, git2::Error> {
+    let (size, _) = git_repo.odb()?.read_header(blob_id)?;
+    Ok(size as u64)
+}
+
+pub(crate) fn read_blob(git_repo: &Repository, blob_id: Oid) -> Result<Vec<u8>, git2::Error> {
+    Ok(git_repo.find_blob(blob_id)?.content().to_vec())
+}
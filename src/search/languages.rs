@@ -0,0 +1,211 @@
+use std::path::Path;
+
+/// The language of files that match none of the rules below.
+pub(crate) const UNKNOWN_LANGUAGE: &str = "Text";
+
+/// A language with its canonical name, as stored in `file_language`, and the ways to
+/// recognize its files. Aliases are the other names `lang:` accepts for it.
+struct Language {
+    name: &'static str,
+    aliases: &'static [&'static str],
+    extensions: &'static [&'static str],
+    filenames: &'static [&'static str],
+    interpreters: &'static [&'static str],
+}
+
+const fn language(
+    name: &'static str,
+    aliases: &'static [&'static str],
+    extensions: &'static [&'static str],
+    filenames: &'static [&'static str],
+    interpreters: &'static [&'static str],
+) -> Language {
+    Language { name, aliases, extensions, filenames, interpreters }
+}
+
+const LANGUAGES: &[Language] = &[
+    language("Rust", &["rs"], &["rs"], &[], &[]),
+    language("Python", &["py", "python3"], &["py", "pyi", "pyw"], &["SConstruct", "SConscript"], &["python", "python2", "python3"]),
+    language("JavaScript", &["js", "node"], &["js", "mjs", "cjs", "jsx"], &["Jakefile"], &["node", "nodejs", "deno"]),
+    language("TypeScript", &["ts"], &["ts", "mts", "cts", "tsx"], &[], &["ts-node", "tsx"]),
+    language("Java", &[], &["java"], &[], &[]),
+    language("Kotlin", &["kt"], &["kt", "kts"], &[], &[]),
+    language("Scala", &[], &["scala", "sc"], &[], &["scala"]),
+    language("Groovy", &[], &["groovy", "gradle"], &["Jenkinsfile"], &["groovy"]),
+    language("C#", &["csharp", "cs"], &["cs", "csx"], &[], &[]),
+    language("Go", &["golang"], &["go"], &[], &[]),
+    language("C", &[], &["c"], &[], &[]),
+    language("C++", &["cpp", "cxx"], &["cpp", "cc", "cxx", "c++", "hpp", "hh", "hxx", "h++", "ipp", "tpp"], &[], &[]),
+    language("Objective-C", &["objc"], &["mm"], &[], &[]),
+    language("Swift", &[], &["swift"], &[], &[]),
+    language("Ruby", &["rb"], &["rb", "rake", "gemspec", "ru"], &["Gemfile", "Rakefile", "Podfile", "Vagrantfile", "Guardfile"], &["ruby", "jruby"]),
+    language("PHP", &[], &["php", "phtml"], &[], &["php"]),
+    language("Perl", &["pl"], &["pm"], &[], &["perl"]),
+    language("Prolog", &[], &[], &[], &["swipl"]),
+    language("Lua", &[], &["lua"], &[], &["lua", "luajit"]),
+    language("R", &[], &["r"], &[".Rprofile"], &["Rscript"]),
+    language("MATLAB", &["matlab", "octave"], &[], &[], &["octave"]),
+    language("Haskell", &["hs"], &["hs", "lhs"], &[], &["runhaskell", "runghc"]),
+    language("Elixir", &["ex"], &["ex", "exs"], &["mix.lock"], &["elixir"]),
+    language("Erlang", &["erl"], &["erl", "hrl"], &["rebar.config"], &["escript"]),
+    language("Clojure", &["clj"], &["clj", "cljs", "cljc", "edn"], &[], &[]),
+    language("OCaml", &["ml"], &["ml", "mli"], &[], &["ocaml"]),
+    language("F#", &["fsharp"], &["fs", "fsi", "fsx"], &[], &[]),
+    language("Dart", &[], &["dart"], &[], &["dart"]),
+    language("Zig", &[], &["zig"], &[], &[]),
+    language("Shell", &["sh", "bash", "zsh"], &["sh", "bash", "zsh", "ksh"], &[".bashrc", ".bash_profile", ".profile", ".zshrc", "PKGBUILD"], &["sh", "bash", "zsh", "ksh", "dash", "ash"]),
+    language("Fish", &[], &["fish"], &[], &["fish"]),
+    language("PowerShell", &["ps1", "pwsh"], &["ps1", "psm1", "psd1"], &[], &["pwsh", "powershell"]),
+    language("Batchfile", &["bat", "cmd"], &["bat", "cmd"], &[], &[]),
+    language("SQL", &[], &["sql"], &[], &[]),
+    language("HTML", &[], &["html", "htm", "xhtml"], &[], &[]),
+    language("CSS", &[], &["css"], &[], &[]),
+    language("SCSS", &["sass"], &["scss", "sass"], &[], &[]),
+    language("Less", &[], &["less"], &[], &[]),
+    language("Vue", &[], &["vue"], &[], &[]),
+    language("Svelte", &[], &["svelte"], &[], &[]),
+    language("XML", &[], &["xml", "xsd", "xsl", "xslt", "svg", "plist", "csproj", "fsproj", "vbproj"], &["pom.xml"], &[]),
+    language("JSON", &[], &["json", "jsonc", "json5"], &[".eslintrc", ".babelrc", "composer.lock"], &[]),
+    language("YAML", &["yml"], &["yaml", "yml"], &[".clang-format"], &[]),
+    language("TOML", &[], &["toml"], &["Cargo.lock", "Pipfile", "poetry.lock"], &[]),
+    language("INI", &[], &["ini", "cfg", "properties"], &[".editorconfig", ".gitconfig"], &[]),
+    language("Markdown", &["md"], &["md", "markdown", "mdx"], &[], &[]),
+    language("reStructuredText", &["rst"], &["rst"], &[], &[]),
+    language("Dockerfile", &["docker"], &["dockerfile"], &["Dockerfile", "Containerfile"], &[]),
+    language("Makefile", &["make"], &["mk", "mak"], &["Makefile", "makefile", "GNUmakefile"], &["make"]),
+    language("CMake", &[], &["cmake"], &["CMakeLists.txt"], &[]),
+    language("Starlark", &["bazel", "bzl"], &["bzl", "star"], &["BUILD", "BUILD.bazel", "WORKSPACE", "WORKSPACE.bazel"], &[]),
+    language("Nix", &[], &["nix"], &[], &[]),
+    language("HCL", &["terraform", "tf"], &["tf", "tfvars", "hcl"], &[], &[]),
+    language("Protocol Buffers", &["proto", "protobuf"], &["proto"], &[], &[]),
+    language("GraphQL", &[], &["graphql", "gql"], &[], &[]),
+    language("Text", &["txt", "plain"], &["txt"], &["LICENSE", "COPYING", "AUTHORS"], &[]),
+];
+
+/// The canonical name of a language named in a `lang:` filter or modeline, like `Rust` for
+/// `rust` or `Shell` for `bash`.
+pub(crate) fn canonical_name(name: &str) -> Option<&'static str> {
+    let name = name.trim();
+    LANGUAGES.iter()
+        .find(|language| {
+            language.name.eq_ignore_ascii_case(name) || language.aliases.iter().any(|alias| alias.eq_ignore_ascii_case(name))
+        })
+        .map(|language| language.name)
+}
+
+/// Detects the language of a file. An editor modeline wins, then well-known file names,
+/// the interpreter of a shebang line, the extension, and finally content heuristics for
+/// the extensions several languages share.
+pub(crate) fn detect_language(file_path: &str, content: &str) -> &'static str {
+    let path = Path::new(file_path);
+    let file_name = path.file_name().map(|file_name| file_name.to_string_lossy()).unwrap_or_default();
+    let ext = path.extension().map(|ext| ext.to_string_lossy().to_lowercase()).unwrap_or_default();
+
+    if let Some(name) = modeline_language(content) {
+        return name;
+    }
+    if let Some(language) = LANGUAGES.iter().find(|language| language.filenames.contains(&file_name.as_ref())) {
+        return language.name;
+    }
+    if file_name.starts_with("Dockerfile.") || file_name.ends_with(".Dockerfile") {
+        return "Dockerfile";
+    }
+    if let Some(name) = shebang_language(content) {
+        return name;
+    }
+    if let Some(name) = ambiguous_extension_language(&ext, content) {
+        return name;
+    }
+    LANGUAGES.iter()
+        .find(|language| language.extensions.contains(&ext.as_str()))
+        .map_or(UNKNOWN_LANGUAGE, |language| language.name)
+}
+
+/// The language set by a vim (`vim: set ft=python:`) or emacs (`-*- mode: ruby -*-`)
+/// modeline in the first or last lines of the content.
+fn modeline_language(content: &str) -> Option<&'static str> {
+    let lines = content.lines().collect::<Vec<_>>();
+    let edge_lines = lines.iter().take(5).chain(lines.iter().skip(lines.len().saturating_sub(5).max(5)));
+    for line in edge_lines {
+        if let Some((_, emacs)) = line.split_once("-*-") {
+            let settings = emacs.split("-*-").next().unwrap_or_default();
+            let mode = settings.split(';')
+                .filter_map(|setting| setting.split_once(':'))
+                .find(|(key, _)| key.trim().eq_ignore_ascii_case("mode"))
+                .map(|(_, mode)| mode.trim())
+                .or_else(|| (!settings.contains(':')).then(|| settings.trim()));
+            if let Some(name) = mode.and_then(canonical_name) {
+                return Some(name);
+            }
+        }
+        let vim = line.find("vim:").or_else(|| line.find("vi:")).map(|start| &line[start..]);
+        if let Some(vim) = vim {
+            let filetype = vim.split(|c: char| c.is_whitespace() || c == ':')
+                .find_map(|setting| setting.strip_prefix("ft=").or_else(|| setting.strip_prefix("filetype=")));
+            if let Some(name) = filetype.and_then(canonical_name) {
+                return Some(name);
+            }
+        }
+    }
+    None
+}
+
+/// The language of the interpreter named by a `#!` line, through `env` if need be.
+fn shebang_language(content: &str) -> Option<&'static str> {
+    let shebang = content.lines().next()?.strip_prefix("#!")?;
+    let mut words = shebang.split_whitespace();
+    let mut interpreter = words.next()?.rsplit('/').next()?;
+    if interpreter == "env" {
+        interpreter = words.find(|word| !word.starts_with('-'))?;
+    }
+    // Versioned interpreters like `python3.11` or `ruby2.7`
+    let interpreter = interpreter.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.');
+    LANGUAGES.iter()
+        .find(|language| language.interpreters.iter().any(|name| name.trim_end_matches(|c: char| c.is_ascii_digit()) == interpreter))
+        .map(|language| language.name)
+}
+
+/// Tells apart the languages sharing an extension by what their files typically contain.
+fn ambiguous_extension_language(ext: &str, content: &str) -> Option<&'static str> {
+    let has_line_starting = |prefixes: &[&str]| content.lines().any(|line| {
+        let line = line.trim_start();
+        prefixes.iter().any(|prefix| line.starts_with(prefix))
+    });
+    match ext {
+        "h" => Some(if has_line_starting(&["@interface", "@protocol", "@property", "#import"]) {
+            "Objective-C"
+        } else if has_line_starting(&["class ", "namespace ", "template", "public:", "private:", "#include <iostream>", "#include <string>", "#include <vector>"]) {
+            "C++"
+        } else {
+            "C"
+        }),
+        "m" => Some(if has_line_starting(&["@interface", "@implementation", "#import", "#include"]) {
+            "Objective-C"
+        } else {
+            "MATLAB"
+        }),
+        "pl" => Some(if has_line_starting(&[":- ", "?- "]) { "Prolog" } else { "Perl" }),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_canonical_language_names() {
+        assert_eq!(detect_language("src/main.rs", "fn main() {}"), "Rust");
+        assert_eq!(detect_language("web/App.tsx", ""), "TypeScript");
+        assert_eq!(detect_language("docker/Dockerfile", "FROM alpine"), "Dockerfile");
+        assert_eq!(detect_language("scripts/deploy", "#!/usr/bin/env bash\necho hi"), "Shell");
+        assert_eq!(detect_language("bin/tool", "#!/usr/bin/python3.11\nprint()"), "Python");
+        assert_eq!(detect_language("conf/app.in", "# vim: set ft=ruby:\nputs 1"), "Ruby");
+        assert_eq!(detect_language("lib/include.h", "#pragma once\nclass Parser {};"), "C++");
+        assert_eq!(detect_language("lib/include.h", "int parse(void);"), "C");
+        assert_eq!(detect_language("notes.unknown", "hello"), UNKNOWN_LANGUAGE);
+        assert_eq!(canonical_name("bash"), Some("Shell"));
+        assert_eq!(canonical_name("TYPESCRIPT"), Some("TypeScript"));
+        assert_eq!(canonical_name("cpp"), Some("C++"));
+    }
+}
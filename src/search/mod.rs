@@ -5,14 +5,91 @@ use tantivy::{self, doc};
 pub use code_schema::CodeFileDto;
 pub use fs_search_engine::FileSearchEngine;
 pub use search_error::SearchError;
+pub use snippet::Snippet;
+pub use facets::Facets;
+pub use ranking::ScoreExplanation;
+pub use symbols::Symbol;
+pub use references::{FileReferences, ReferenceOptions, ReferenceTarget, References};
+pub use code_intel::{CodeIntel, CodeIntelOptions, Location};
+pub use file_content::SkippedFile;
+pub(crate) use code_tokenizer::CODE_TOKENIZER_NAME;
 
 mod fs_search_engine;
 mod code_schema;
 mod search_error;
+mod git_repo;
+mod repo_walker;
+mod file_content;
+mod chunker;
+mod snippet;
+mod code_tokenizer;
+mod trigram;
+mod regex_query;
+mod query_language;
+mod cursor;
+mod facets;
+mod ranking;
+mod symbols;
+mod references;
+mod code_intel;
+mod languages;
+
+/// `text` matches words and identifiers through the query parser, `literal` finds the
+/// query as an exact substring of the file content, and `regex` matches it as a regular
+/// expression against the file content.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SearchMode {
+    #[default]
+    Text,
+    Literal,
+    Regex,
+}
+
+/// The order of the results. Scores, modification times and sizes are sorted highest
+/// first, paths alphabetically.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SortBy {
+    #[default]
+    Score,
+    Modified,
+    Size,
+    Path,
+}
 
 pub struct SearchOptions {
     pub query: String,
+    pub mode: SearchMode,
+    pub sort_by: SortBy,
     pub limit: usize,
+    pub offset: usize,
+    /// Continues after the page a previous search returned this cursor with, instead of
+    /// at `offset`.
+    pub cursor: Option<String>,
+    /// Returns the whole file content with each result, instead of only the snippets.
+    pub include_content: bool,
+    pub context_lines: usize,
+    /// Returns how the score of each result was computed.
+    pub explain: bool,
+}
+
+#[cfg(test)]
+impl SearchOptions {
+    /// Text search options for the query, tests override the fields they care about.
+    pub(crate) fn for_query(query: &str) -> Self {
+        SearchOptions {
+            query: query.to_string(),
+            mode: SearchMode::Text,
+            sort_by: SortBy::Score,
+            limit: 10,
+            offset: 0,
+            cursor: None,
+            include_content: false,
+            context_lines: 0,
+            explain: false,
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -20,9 +97,37 @@ pub struct ResultItem  {
     pub _score: f32,
     #[serde(flatten)]
     pub data: CodeFileDto,
+    pub chunks: Vec<MatchedChunk>,
+    pub snippets: Vec<Snippet>,
+    /// The definitions in the file that `sym:` filters of the query matched.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub symbols: Vec<Symbol>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub explanation: Option<ScoreExplanation>,
+}
+
+/// A line range of the result file that matched the query, best matches first.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MatchedChunk {
+    pub _score: f32,
+    pub start_line: usize,
+    pub end_line: usize,
+}
+
+/// A page of results, with the number of matching files across all pages.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SearchResults {
+    pub results: Vec<ResultItem>,
+    pub total: usize,
+    /// The position of the first result of the page among all results.
+    pub offset: usize,
+    pub next_cursor: Option<String>,
+    pub facets: Facets,
 }
 
 #[async_trait]
 pub trait SearchEngine {
-    async fn search(&self, options: SearchOptions) -> Result<Vec<ResultItem>, SearchError>;
+    async fn search(&self, options: SearchOptions) -> Result<SearchResults, SearchError>;
+    async fn find_references(&self, options: ReferenceOptions) -> Result<References, SearchError>;
+    async fn code_intel(&self, options: CodeIntelOptions) -> Result<CodeIntel, SearchError>;
 }
\ No newline at end of file
@@ -0,0 +1,240 @@
+use crate::search::search_error::SearchError;
+
+/// A search query split into the text to search for and the `key:value` filters around it,
+/// like `repo:backend lang:rust -path:test parse`.
+#[derive(Debug, PartialEq)]
+pub(crate) struct ParsedQuery {
+    pub(crate) text: String,
+    pub(crate) filters: Vec<Filter>,
+    /// Set by `case:yes` or `case:no`, matching is case-insensitive by default.
+    pub(crate) case_sensitive: bool,
+    /// The char offset in the query of each char of the text.
+    text_positions: Vec<usize>,
+}
+
+impl ParsedQuery {
+    /// The char offset in the query of a char offset in the text, for pointing errors found
+    /// in the text at the query the user typed.
+    pub(crate) fn query_position(&self, text_position: usize) -> usize {
+        match self.text_positions.get(text_position) {
+            Some(position) => *position,
+            None => match self.text_positions.last() {
+                Some(last) => last + 1 + text_position - self.text_positions.len(),
+                None => text_position,
+            },
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub(crate) struct Filter {
+    pub(crate) kind: FilterKind,
+    pub(crate) value: FilterValue,
+    /// `-` prefixed filters exclude what they match.
+    pub(crate) negated: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum FilterKind {
+    Repo,
+    Lang,
+    Path,
+    File,
+    Ext,
+    Branch,
+    Modified,
+    Size,
+    Symbol,
+}
+
+impl FilterKind {
+    fn from_key(key: &str) -> Option<Self> {
+        match key {
+            "repo" => Some(FilterKind::Repo),
+            "lang" => Some(FilterKind::Lang),
+            "path" => Some(FilterKind::Path),
+            "file" => Some(FilterKind::File),
+            "ext" => Some(FilterKind::Ext),
+            "branch" => Some(FilterKind::Branch),
+            "modified" => Some(FilterKind::Modified),
+            "size" => Some(FilterKind::Size),
+            "sym" => Some(FilterKind::Symbol),
+            _ => None,
+        }
+    }
+
+    /// Path, file and symbol filters accept `/regex/` values, the others only match exactly.
+    fn allows_regex(&self) -> bool {
+        matches!(self, FilterKind::Path | FilterKind::File | FilterKind::Symbol)
+    }
+
+    /// Size and modification time filters compare, like `size:<10kb` or `modified:>2026-01-01`.
+    fn is_range(&self) -> bool {
+        matches!(self, FilterKind::Modified | FilterKind::Size)
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub(crate) enum FilterValue {
+    Text(String),
+    Regex(String),
+    Range(Comparison, String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Comparison {
+    Less,
+    LessOrEqual,
+    Equal,
+    GreaterOrEqual,
+    Greater,
+}
+
+impl Comparison {
+    /// Splits a leading comparison operator off a value, no operator means equal.
+    fn split(value: &str) -> (Self, &str) {
+        let operators = [
+            ("<=", Comparison::LessOrEqual),
+            (">=", Comparison::GreaterOrEqual),
+            ("<", Comparison::Less),
+            (">", Comparison::Greater),
+            ("=", Comparison::Equal),
+        ];
+        operators.iter()
+            .find_map(|(operator, comparison)| value.strip_prefix(operator).map(|value| (*comparison, value)))
+            .unwrap_or((Comparison::Equal, value))
+    }
+}
+
+/// Splits the filters off a query. Values can be quoted to contain spaces, and the text
+/// keeps its original spacing, which matters for literal searches.
+pub(crate) fn parse_query(query: &str) -> Result<ParsedQuery, SearchError> {
+    let mut filters = Vec::new();
+    let mut case_sensitive = false;
+    let mut text = Vec::new();
+    let mut last_end = 0;
+    for (start, end) in split_words(query) {
+        let word = &query[start..end];
+        let (negated, filter) = match word.strip_prefix('-') {
+            Some(filter) => (true, filter),
+            None => (false, word),
+        };
+        let Some((key, value)) = filter.split_once(':') else {
+            continue;
+        };
+        let value = unquote(value);
+        if value.is_empty() {
+            continue;
+        }
+
+        if key == "case" && !negated {
+            case_sensitive = match value {
+                "yes" | "true" => true,
+                "no" | "false" => false,
+                _ => return Err(SearchError::invalid_query_at(
+                    format!("Invalid case value '{}', expected yes or no", value),
+                    query[..start].chars().count(),
+                )),
+            };
+        } else if let Some(kind) = FilterKind::from_key(key) {
+            let value = if kind.is_range() {
+                let (comparison, value) = Comparison::split(value);
+                FilterValue::Range(comparison, value.to_string())
+            } else {
+                match value.strip_prefix('/').and_then(|value| value.strip_suffix('/')) {
+                    Some(pattern) if kind.allows_regex() => FilterValue::Regex(pattern.to_string()),
+                    _ => FilterValue::Text(value.to_string()),
+                }
+            };
+            filters.push(Filter { kind, value, negated });
+        } else {
+            continue;
+        }
+
+        push_text(&mut text, query, last_end, start);
+        last_end = end;
+    }
+    push_text(&mut text, query, last_end, query.len());
+
+    let text_start = text.iter().position(|(_, c)| !c.is_whitespace()).unwrap_or(text.len());
+    let text_end = text.iter().rposition(|(_, c)| !c.is_whitespace()).map_or(text_start, |index| index + 1);
+    let text = &text[text_start..text_end];
+    Ok(ParsedQuery {
+        text: text.iter().map(|(_, c)| c).collect(),
+        filters,
+        case_sensitive,
+        text_positions: text.iter().map(|(position, _)| *position).collect(),
+    })
+}
+
+/// Appends the chars of `query[start..end]` to the text, along with their char offsets in
+/// the query.
+fn push_text(text: &mut Vec<(usize, char)>, query: &str, start: usize, end: usize) {
+    let query_position = query[..start].chars().count();
+    text.extend(query[start..end].chars().enumerate().map(|(index, c)| (query_position + index, c)));
+}
+
+/// The byte ranges of the whitespace separated words, not splitting inside double quotes.
+fn split_words(query: &str) -> Vec<(usize, usize)> {
+    let mut words = Vec::new();
+    let mut word_start = None;
+    let mut in_quotes = false;
+    for (index, c) in query.char_indices() {
+        if c == '"' {
+            in_quotes = !in_quotes;
+        }
+        match word_start {
+            None if !c.is_whitespace() => word_start = Some(index),
+            Some(start) if c.is_whitespace() && !in_quotes => {
+                words.push((start, index));
+                word_start = None;
+            }
+            _ => {}
+        }
+    }
+    if let Some(start) = word_start {
+        words.push((start, query.len()));
+    }
+    words
+}
+
+fn unquote(value: &str) -> &str {
+    value.strip_prefix('"')
+        .and_then(|value| value.strip_suffix('"'))
+        .unwrap_or(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_filters_from_text() {
+        let parsed = parse_query(r#"repo:backend parse  query -path:/_test\.rs$/ file:"My File.ts" size:<=10kb case:yes"#).unwrap();
+        assert_eq!(parsed.text, "parse  query");
+        assert!(parsed.case_sensitive);
+        assert_eq!(parsed.filters, vec![
+            Filter { kind: FilterKind::Repo, value: FilterValue::Text("backend".to_string()), negated: false },
+            Filter { kind: FilterKind::Path, value: FilterValue::Regex(r"_test\.rs$".to_string()), negated: true },
+            Filter { kind: FilterKind::File, value: FilterValue::Text("My File.ts".to_string()), negated: false },
+            Filter { kind: FilterKind::Size, value: FilterValue::Range(Comparison::LessOrEqual, "10kb".to_string()), negated: false },
+        ]);
+    }
+
+    #[test]
+    fn maps_text_positions_to_the_query() {
+        let parsed = parse_query("  lang:rust fé  repo:x bar( ").unwrap();
+        assert_eq!(parsed.text, "fé   bar(");
+        assert_eq!(parsed.query_position(0), 12);
+        assert_eq!(parsed.query_position(5), 23);
+        assert_eq!(parsed.query_position(8), 26);
+        assert_eq!(parsed.query_position(9), 27);
+    }
+
+    #[test]
+    fn invalid_case_value_reports_its_position() {
+        let err = parse_query("parse é case:maybe").unwrap_err();
+        assert_eq!(err.get_code(), "invalid_query");
+        assert_eq!(err.position(), Some(8));
+    }
+}
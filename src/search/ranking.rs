@@ -0,0 +1,92 @@
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use serde::{Deserialize, Serialize};
+
+use crate::config::Ranking;
+
+const MILLIS_PER_DAY: f32 = 24.0 * 60.0 * 60.0 * 1000.0;
+
+/// Adjusts the scores of matching files by where they live and how recently they changed,
+/// as configured in the `ranking` section. Field boosts are applied by the query itself.
+pub(crate) struct Ranker {
+    demoted: GlobSet,
+    demotion: f32,
+    recency_boost: f32,
+    recency_half_life_days: f32,
+}
+
+/// The factors a file's base score is multiplied with.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct RankingFactors {
+    pub(crate) demotion: f32,
+    pub(crate) recency: f32,
+}
+
+impl RankingFactors {
+    pub(crate) fn apply(&self, score: f32) -> f32 {
+        score * self.demotion * self.recency
+    }
+}
+
+/// How the score of a result came about, returned when a search asks to explain scores.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ScoreExplanation {
+    /// BM25 with field boosts for text searches, the number of matches otherwise.
+    pub base_score: f32,
+    pub demotion: f32,
+    pub recency: f32,
+    pub score: f32,
+    /// How the index computed the base score of the best matching document, for text searches.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub details: Option<serde_json::Value>,
+}
+
+impl ScoreExplanation {
+    pub(crate) fn new(base_score: f32, factors: RankingFactors, details: Option<serde_json::Value>) -> Self {
+        ScoreExplanation {
+            base_score,
+            demotion: factors.demotion,
+            recency: factors.recency,
+            score: factors.apply(base_score),
+            details,
+        }
+    }
+}
+
+impl Ranker {
+    pub(crate) fn new(ranking: &Ranking) -> Result<Self, globset::Error> {
+        let mut builder = GlobSetBuilder::new();
+        for pattern in &ranking.demoted_patterns {
+            builder.add(Glob::new(pattern)?);
+        }
+        Ok(Ranker {
+            demoted: builder.build()?,
+            demotion: ranking.demotion,
+            recency_boost: ranking.recency_boost,
+            recency_half_life_days: ranking.recency_half_life_days,
+        })
+    }
+
+    /// The factors for a file at `file_path`, last modified at `modified`, both in milliseconds
+    /// since the epoch like `now`.
+    pub(crate) fn factors(&self, file_path: &str, modified: i64, now: i64) -> RankingFactors {
+        let demotion = if self.demoted.is_match(file_path) { self.demotion } else { 1.0 };
+        let age_days = (now - modified).max(0) as f32 / MILLIS_PER_DAY;
+        let recency = 1.0 + self.recency_boost * 0.5f32.powf(age_days / self.recency_half_life_days);
+        RankingFactors { demotion, recency }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn demotes_tests_and_boosts_recent_files() {
+        let ranker = Ranker::new(&Ranking::default()).unwrap();
+        let now = 1_000 * MILLIS_PER_DAY as i64;
+        assert_eq!(ranker.factors("src/parser.rs", now, now), RankingFactors { demotion: 1.0, recency: 1.2 });
+        assert_eq!(ranker.factors("src/parser_test.go", now, now).demotion, 0.5);
+        assert_eq!(ranker.factors("vendor/lib/parser.rs", now, now).demotion, 0.5);
+        assert_eq!(ranker.factors("src/parser.rs", now - 90 * MILLIS_PER_DAY as i64, now).recency, 1.1);
+    }
+}
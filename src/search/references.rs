@@ -0,0 +1,170 @@
+use serde::{Deserialize, Serialize};
+use tantivy::tokenizer::{RemoveLongFilter, TextAnalyzer, Token, TokenStream, Tokenizer};
+
+use crate::search::Symbol;
+
+pub(crate) const IDENTIFIER_TOKENIZER_NAME: &str = "identifier";
+
+/// What to find references of: a symbol name, or the identifier at a 1-based line and
+/// column of an indexed file.
+#[derive(Debug, Clone)]
+pub enum ReferenceTarget {
+    Name(String),
+    Position {
+        repo: String,
+        path: String,
+        line: usize,
+        column: usize,
+    },
+}
+
+pub struct ReferenceOptions {
+    pub target: ReferenceTarget,
+    /// How many files are returned at most, files with definitions first.
+    pub limit: usize,
+}
+
+/// The definitions and uses of a name across all repos, grouped by file.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct References {
+    pub name: String,
+    pub files: Vec<FileReferences>,
+    pub total_definitions: usize,
+    pub total_references: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FileReferences {
+    pub file_id: String,
+    pub repo_name: String,
+    pub file_path: String,
+    pub definitions: Vec<Symbol>,
+    pub references: Vec<Reference>,
+}
+
+/// A use of the name, with 1-based line and char column.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Reference {
+    pub line: usize,
+    pub column: usize,
+    pub text: String,
+}
+
+/// Identifiers are indexed with their case, so references match the name exactly.
+pub(crate) fn create_identifier_analyzer() -> TextAnalyzer {
+    TextAnalyzer::builder(IdentifierTokenizer)
+        .filter(RemoveLongFilter::limit(100))
+        .build()
+}
+
+/// Emits every identifier of the text as is, with its 0-based line number as position. The
+/// index then records on which lines of a file an identifier occurs.
+#[derive(Clone)]
+pub(crate) struct IdentifierTokenizer;
+
+pub(crate) struct IdentifierTokenStream {
+    tokens: Vec<Token>,
+    index: usize,
+}
+
+impl Tokenizer for IdentifierTokenizer {
+    type TokenStream<'a> = IdentifierTokenStream;
+
+    fn token_stream<'a>(&'a mut self, text: &'a str) -> Self::TokenStream<'a> {
+        let mut tokens = Vec::new();
+        let mut line_start = 0;
+        for (line_number, line) in text.split('\n').enumerate() {
+            for (start, identifier) in identifiers(line) {
+                tokens.push(Token {
+                    offset_from: line_start + start,
+                    offset_to: line_start + start + identifier.len(),
+                    position: line_number,
+                    text: identifier.to_string(),
+                    position_length: 1,
+                });
+            }
+            line_start += line.len() + 1;
+        }
+        IdentifierTokenStream { tokens, index: 0 }
+    }
+}
+
+impl TokenStream for IdentifierTokenStream {
+    fn advance(&mut self) -> bool {
+        self.index += 1;
+        self.index <= self.tokens.len()
+    }
+
+    fn token(&self) -> &Token {
+        &self.tokens[self.index - 1]
+    }
+
+    fn token_mut(&mut self) -> &mut Token {
+        &mut self.tokens[self.index - 1]
+    }
+}
+
+fn is_identifier_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+/// The identifiers of a line with their byte offsets. Runs of word characters starting with
+/// a digit are numbers, not identifiers.
+fn identifiers(line: &str) -> Vec<(usize, &str)> {
+    let mut identifiers = Vec::new();
+    let mut start = None;
+    for (index, c) in line.char_indices().chain(std::iter::once((line.len(), ' '))) {
+        match start {
+            None if is_identifier_char(c) => start = Some(index),
+            Some(identifier_start) if !is_identifier_char(c) => {
+                let identifier = &line[identifier_start..index];
+                if !identifier.starts_with(|c: char| c.is_ascii_digit()) {
+                    identifiers.push((identifier_start, identifier));
+                }
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    identifiers
+}
+
+/// The identifier covering a 1-based char column of the line.
+pub(crate) fn identifier_at(line: &str, column: usize) -> Option<&str> {
+    let byte_offset = line.char_indices().nth(column.checked_sub(1)?)?.0;
+    identifiers(line).into_iter()
+        .find(|(start, identifier)| (*start..start + identifier.len()).contains(&byte_offset))
+        .map(|(_, identifier)| identifier)
+}
+
+/// The 1-based char columns at which the name occurs as a whole identifier in the line.
+pub(crate) fn identifier_columns(line: &str, name: &str) -> Vec<usize> {
+    identifiers(line).into_iter()
+        .filter(|(_, identifier)| *identifier == name)
+        .map(|(start, _)| line[..start].chars().count() + 1)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_identifiers_by_line_and_column() {
+        let mut tokenizer = IdentifierTokenizer;
+        let mut token_stream = tokenizer.token_stream("let dto = create_dto(1);\n  create_dto(2x)");
+        let mut tokens = Vec::new();
+        while token_stream.advance() {
+            tokens.push((token_stream.token().text.clone(), token_stream.token().position));
+        }
+        assert_eq!(tokens, vec![
+            ("let".to_string(), 0),
+            ("dto".to_string(), 0),
+            ("create_dto".to_string(), 0),
+            ("create_dto".to_string(), 1),
+        ]);
+        assert_eq!(identifier_at("let dto = create_dto(1);", 14), Some("create_dto"));
+        assert_eq!(identifier_at("let dto = create_dto(1);", 9), None);
+        assert_eq!(identifier_columns("  create_dto(create_dtos)", "create_dto"), vec![3]);
+    }
+}
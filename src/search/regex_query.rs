@@ -0,0 +1,161 @@
+use std::collections::BTreeSet;
+
+use regex::Regex;
+use regex_syntax::hir::{Class, Hir, HirKind};
+
+use crate::search::search_error::SearchError;
+use crate::search::trigram::TrigramQuery;
+
+/// Above this many alternatives, the exact strings a pattern can match are no longer tracked.
+const MAX_EXACT_SET_SIZE: usize = 16;
+
+/// Character classes with up to this many characters are expanded into their characters.
+const MAX_CLASS_SIZE: usize = 8;
+
+/// Compiles a pattern for searching file content, `^` and `$` match at line boundaries as
+/// in grep. Also returns the trigrams a file needs to contain to possibly match, which are
+/// lowercased and so hold whatever the case sensitivity.
+pub(crate) fn compile(pattern: &str, case_sensitive: bool) -> Result<(Regex, TrigramQuery), SearchError> {
+    let hir = regex_syntax::ParserBuilder::new()
+        .multi_line(true)
+        .build()
+        .parse(pattern)
+        .map_err(|err| SearchError::from((pattern, err)))?;
+    let regex = regex::RegexBuilder::new(pattern)
+        .multi_line(true)
+        .case_insensitive(!case_sensitive)
+        .build()?;
+    Ok((regex, analyze(&hir).into_query()))
+}
+
+/// What is known about the strings a pattern matches: the complete set of them if it is
+/// small, and a trigram query that holds for all of them.
+struct PatternInfo {
+    exact: Option<BTreeSet<String>>,
+    query: TrigramQuery,
+}
+
+impl PatternInfo {
+    fn any() -> Self {
+        PatternInfo {
+            exact: None,
+            query: TrigramQuery::All,
+        }
+    }
+
+    fn exact(strings: BTreeSet<String>) -> Self {
+        PatternInfo {
+            exact: Some(strings),
+            query: TrigramQuery::All,
+        }
+    }
+
+    fn into_query(self) -> TrigramQuery {
+        let exact_query = match self.exact {
+            Some(strings) => TrigramQuery::or(strings.iter()
+                .map(|string| TrigramQuery::literal(string))
+                .collect()),
+            None => TrigramQuery::All,
+        };
+        TrigramQuery::and(vec![self.query, exact_query])
+    }
+
+    fn concat(self, next: PatternInfo) -> Self {
+        match (self.exact, next.exact) {
+            (Some(prefixes), Some(suffixes)) if prefixes.len() * suffixes.len() <= MAX_EXACT_SET_SIZE => {
+                let strings = prefixes.iter()
+                    .flat_map(|prefix| suffixes.iter().map(move |suffix| format!("{}{}", prefix, suffix)))
+                    .collect();
+                PatternInfo {
+                    exact: Some(strings),
+                    query: TrigramQuery::and(vec![self.query, next.query]),
+                }
+            }
+            (exact, next_exact) => {
+                let info = PatternInfo { exact, query: self.query };
+                let next = PatternInfo { exact: next_exact, query: next.query };
+                PatternInfo {
+                    exact: None,
+                    query: TrigramQuery::and(vec![info.into_query(), next.into_query()]),
+                }
+            }
+        }
+    }
+}
+
+fn analyze(hir: &Hir) -> PatternInfo {
+    match hir.kind() {
+        HirKind::Empty | HirKind::Look(_) => PatternInfo::exact(BTreeSet::from(["".to_string()])),
+        HirKind::Literal(literal) => match std::str::from_utf8(&literal.0) {
+            Ok(text) => PatternInfo::exact(BTreeSet::from([text.to_string()])),
+            Err(_) => PatternInfo::any(),
+        },
+        HirKind::Class(Class::Unicode(class)) => {
+            let size = class.iter()
+                .map(|range| range.end() as usize - range.start() as usize + 1)
+                .sum::<usize>();
+            if size > MAX_CLASS_SIZE {
+                return PatternInfo::any();
+            }
+            let chars = class.iter()
+                .flat_map(|range| range.start()..=range.end())
+                .map(|c| c.to_string())
+                .collect();
+            PatternInfo::exact(chars)
+        }
+        HirKind::Class(Class::Bytes(_)) => PatternInfo::any(),
+        HirKind::Capture(capture) => analyze(&capture.sub),
+        HirKind::Repetition(repetition) => {
+            if repetition.min == 0 {
+                return PatternInfo::any();
+            }
+            let info = analyze(&repetition.sub);
+            if repetition.min == 1 && repetition.max == Some(1) {
+                return info;
+            }
+            PatternInfo {
+                exact: None,
+                query: info.into_query(),
+            }
+        }
+        HirKind::Concat(subs) => subs.iter()
+            .map(analyze)
+            .fold(PatternInfo::exact(BTreeSet::from(["".to_string()])), PatternInfo::concat),
+        HirKind::Alternation(subs) => {
+            let infos = subs.iter().map(analyze).collect::<Vec<_>>();
+            let exact_count = infos.iter()
+                .map(|info| info.exact.as_ref().map(|exact| exact.len()))
+                .sum::<Option<usize>>();
+            match exact_count {
+                Some(count) if count <= MAX_EXACT_SET_SIZE && infos.iter().all(|info| info.query == TrigramQuery::All) => {
+                    PatternInfo::exact(infos.into_iter().flat_map(|info| info.exact.unwrap()).collect())
+                }
+                _ => PatternInfo {
+                    exact: None,
+                    query: TrigramQuery::or(infos.into_iter().map(PatternInfo::into_query).collect()),
+                },
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn trigram_query(pattern: &str) -> TrigramQuery {
+        compile(pattern, true).unwrap().1
+    }
+
+    fn trigrams(trigrams: &[&str]) -> TrigramQuery {
+        TrigramQuery::and(trigrams.iter().map(|trigram| TrigramQuery::Trigram(trigram.to_string())).collect())
+    }
+
+    #[test]
+    fn extracts_required_trigrams() {
+        assert_eq!(trigram_query(r"fn \w+\(\)"), trigrams(&["fn "]));
+        assert_eq!(trigram_query(r"Parser::new"), trigrams(&["::n", ":ne", "ars", "er:", "new", "par", "r::", "rse", "ser"]));
+        assert_eq!(trigram_query(r"foo|barbaz"), TrigramQuery::or(vec![trigrams(&["arb", "bar", "baz", "rba"]), trigrams(&["foo"])]));
+        assert_eq!(trigram_query(r"a.*b"), TrigramQuery::All);
+    }
+}
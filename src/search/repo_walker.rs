@@ -0,0 +1,233 @@
+use std::path::Path;
+
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use ignore::{Match, Walk, WalkBuilder};
+
+use crate::config;
+
+const GIT_DIR_NAME: &str = ".git";
+
+/// Decides which paths of a repo get indexed from its glob include and exclude patterns.
+/// Paths are matched relative to the repo root.
+#[derive(Clone)]
+pub(crate) struct RepoFileFilter {
+    includes: Option<GlobSet>,
+    excludes: GlobSet,
+}
+
+impl RepoFileFilter {
+    pub(crate) fn new(repo: &config::Repo) -> Result<Self, globset::Error> {
+        let (include_patterns, exclude_patterns) = repo.file_patterns();
+
+        let includes = if include_patterns.is_empty() {
+            None
+        } else {
+            Some(build_glob_set(&include_patterns)?)
+        };
+        let excludes = build_glob_set(&exclude_patterns)?;
+        Ok(RepoFileFilter { includes, excludes })
+    }
+
+    /// Whether the path, or a directory that would contain it, matches an exclude pattern.
+    /// Whatever is inside a `.git` directory is always excluded.
+    pub(crate) fn is_excluded(&self, relative_path: &Path, is_dir: bool) -> bool {
+        if relative_path.as_os_str().is_empty() {
+            return false;
+        }
+        if relative_path.components().any(|component| component.as_os_str() == GIT_DIR_NAME) {
+            return true;
+        }
+        if self.excludes.is_match(relative_path) {
+            return true;
+        }
+        // `**/generated/**` style patterns only match what is below a directory, so probe
+        // with a child name that no pattern can name literally
+        is_dir && self.excludes.is_match(relative_path.join("\0"))
+    }
+
+    pub(crate) fn is_included(&self, relative_path: &Path) -> bool {
+        if self.is_excluded(relative_path, false) {
+            return false;
+        }
+        match &self.includes {
+            Some(includes) => includes.is_match(relative_path),
+            None => true,
+        }
+    }
+}
+
+/// Patterns without a `/` match at any depth, like in `.gitignore`, while patterns with one
+/// are anchored at the repo root. `*` stops at `/`, only `**` crosses directories. Every
+/// pattern also matches the contents of a directory it names, so `node_modules` excludes
+/// everything below such a directory.
+fn build_glob_set(patterns: &[String]) -> Result<GlobSet, globset::Error> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        let pattern = pattern.trim_end_matches('/');
+        let pattern = match pattern.strip_prefix('/') {
+            Some(anchored_pattern) => anchored_pattern.to_string(),
+            None if !pattern.contains('/') => format!("**/{}", pattern),
+            None => pattern.to_string(),
+        };
+        builder.add(build_glob(&pattern)?);
+        builder.add(build_glob(&format!("{}/**", pattern))?);
+    }
+    builder.build()
+}
+
+fn build_glob(pattern: &str) -> Result<globset::Glob, globset::Error> {
+    GlobBuilder::new(pattern).literal_separator(true).build()
+}
+
+/// Walks `root` inside a repo, honouring nested `.gitignore` and `.ignore` files,
+/// `.git/info/exclude` and the global git excludes unless the repo opts out. Hidden files
+/// are walked either way, only the `.git` directory is left out.
+pub(crate) fn build_walker(repo: &config::Repo, file_filter: &RepoFileFilter, root: &Path) -> Walk {
+    let respect_ignore_files = repo.respect_ignore_files;
+    let repo_root = Path::new(&repo.path).to_path_buf();
+    let file_filter = file_filter.clone();
+    WalkBuilder::new(root)
+        .hidden(false)
+        .parents(respect_ignore_files)
+        .ignore(respect_ignore_files)
+        .git_ignore(respect_ignore_files)
+        .git_exclude(respect_ignore_files)
+        .git_global(respect_ignore_files)
+        // Repos checked out from other version control systems still carry .gitignore files
+        .require_git(false)
+        .filter_entry(move |entry| {
+            let is_dir = entry.file_type().is_some_and(|file_type| file_type.is_dir());
+            let relative_path = entry.path().strip_prefix(&repo_root).unwrap_or(entry.path());
+            !file_filter.is_excluded(relative_path, is_dir)
+        })
+        .build()
+}
+
+/// Checks a single path against the ignore files of its repo, used for paths that are not
+/// reached through `build_walker`. Deeper ignore files take precedence over shallower ones,
+/// and a path is ignored as soon as one of its parent directories is.
+pub(crate) fn is_ignored(repo: &config::Repo, path: &Path) -> bool {
+    if !repo.respect_ignore_files {
+        return false;
+    }
+    let root = Path::new(&repo.path);
+    let relative_path = match path.strip_prefix(root) {
+        Ok(relative_path) => relative_path,
+        Err(_) => return false,
+    };
+
+    let mut matchers = vec![Gitignore::global().0];
+    matchers.push(build_matcher(root, &[root.join(".git").join("info").join("exclude")]));
+
+    let mut dir = root.to_path_buf();
+    for component in relative_path.components() {
+        matchers.push(build_matcher(&dir, &[dir.join(".gitignore"), dir.join(".ignore")]));
+        let candidate = dir.join(component);
+        let is_dir = candidate.is_dir();
+        for matcher in matchers.iter().rev() {
+            match matcher.matched(&candidate, is_dir) {
+                Match::Ignore(_) => return true,
+                Match::Whitelist(_) => break,
+                Match::None => {}
+            }
+        }
+        dir = candidate;
+    }
+    false
+}
+
+fn build_matcher(dir: &Path, ignore_files: &[std::path::PathBuf]) -> Gitignore {
+    let mut builder = GitignoreBuilder::new(dir);
+    for ignore_file in ignore_files.iter().filter(|ignore_file| ignore_file.is_file()) {
+        if let Some(err) = builder.add(ignore_file) {
+            log::warn!("Failed to read ignore file {:?}: {}", ignore_file, err);
+        }
+    }
+    builder.build().unwrap_or_else(|err| {
+        log::warn!("Invalid ignore rules in {:?}: {}", dir, err);
+        Gitignore::empty()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_file_filter(patterns: &str) -> RepoFileFilter {
+        let repo: config::Repo = serde_yaml::from_str(&format!("name: test\ntype: fs\npath: /repo\n{}", patterns)).unwrap();
+        RepoFileFilter::new(&repo).unwrap()
+    }
+
+    #[test]
+    fn glob_patterns_select_repo_files() {
+        let file_filter = create_file_filter(r#"
+include_patterns: ["*.rs", "Makefile", "Dockerfile", "/docs/*.md", "!*_test.rs"]
+exclude_patterns: ["target", "/build", "src/generated/"]
+"#);
+        let included = [
+            "main.rs",
+            "src/deep/lib.rs",
+            "src/targeting.rs",
+            "Makefile",
+            "tools/Makefile",
+            "docker/Dockerfile",
+            "docs/guide.md",
+            "lib/build/mod.rs",
+        ];
+        for path in included {
+            assert!(file_filter.is_included(Path::new(path)), "{} should be included", path);
+        }
+        let not_included = [
+            "README.md",
+            "docs/api/guide.md",
+            "Makefile.bak",
+            "parser_test.rs",
+            "target/debug/build.rs",
+            "crates/a/target/out.rs",
+            "build/out.rs",
+            "src/generated/api.rs",
+        ];
+        for path in not_included {
+            assert!(!file_filter.is_included(Path::new(path)), "{} should not be included", path);
+        }
+        assert!(file_filter.is_excluded(Path::new("crates/a/target"), true));
+        assert!(!file_filter.is_excluded(Path::new("src"), true));
+    }
+
+    #[test]
+    fn legacy_settings_translate_to_globs() {
+        let file_filter = create_file_filter("allowed_file_extensions: [rs]\nskip_patterns: [vendor]");
+        assert!(file_filter.is_included(Path::new("src/lib.rs")));
+        assert!(!file_filter.is_included(Path::new("src/lib.py")));
+        assert!(!file_filter.is_included(Path::new("vendor/lib.rs")));
+    }
+
+    #[test]
+    fn walker_skips_git_directory_but_not_hidden_files() {
+        let repo_dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(repo_dir.path().join(".git/refs")).unwrap();
+        std::fs::create_dir_all(repo_dir.path().join(".github")).unwrap();
+        std::fs::write(repo_dir.path().join(".git/config"), "[core]").unwrap();
+        std::fs::write(repo_dir.path().join(".git/refs/main"), "0000").unwrap();
+        std::fs::write(repo_dir.path().join(".github/ci.yml"), "on: push").unwrap();
+        std::fs::write(repo_dir.path().join("a.txt"), "a").unwrap();
+
+        for respect_ignore_files in [true, false] {
+            let repo: config::Repo = serde_yaml::from_str(&format!(
+                "name: test\ntype: fs\npath: \"{}\"\nrespect_ignore_files: {}",
+                repo_dir.path().display(),
+                respect_ignore_files,
+            )).unwrap();
+            let file_filter = RepoFileFilter::new(&repo).unwrap();
+            let mut file_paths = build_walker(&repo, &file_filter, repo_dir.path())
+                .filter_map(|entry| entry.ok())
+                .filter(|entry| entry.file_type().is_some_and(|file_type| file_type.is_file()))
+                .map(|entry| entry.path().strip_prefix(repo_dir.path()).unwrap().to_string_lossy().to_string())
+                .collect::<Vec<_>>();
+            file_paths.sort();
+            assert_eq!(file_paths, vec![".github/ci.yml", "a.txt"]);
+            assert!(file_filter.is_excluded(Path::new(".git/config"), false));
+        }
+    }
+}
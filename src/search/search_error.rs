@@ -1,64 +1,253 @@
+use std::error::Error;
 use std::fmt;
-use std::fmt::Error;
 
 use tantivy::query::QueryParserError;
+use tantivy::TantivyError;
 use tokio::task;
 
+use crate::search::query_language::ParsedQuery;
+
+type Source = Box<dyn Error + Send + Sync>;
+
+/// What went wrong in a search engine call, by who is to blame: the request (invalid query,
+/// not found) or the engine (index unavailable, IO, internal).
 #[derive(Debug)]
-pub struct SearchError {
-    pub(crate) error: String,
+pub enum SearchError {
+    /// The query or another request parameter is malformed. `position` is the char offset
+    /// in the query the problem was found at, when known.
+    InvalidQuery {
+        message: String,
+        position: Option<usize>,
+        source: Option<Source>,
+    },
+    /// A file, symbol or position the request refers to is not in the index.
+    NotFound {
+        message: String,
+    },
+    /// The index can't be opened or read, or was written with another schema.
+    IndexUnavailable {
+        message: String,
+        source: Option<Source>,
+    },
+    /// Reading repos or precise index dumps failed.
+    Io {
+        message: String,
+        source: Option<Source>,
+    },
+    Internal {
+        message: String,
+        source: Option<Source>,
+    },
+}
+
+impl SearchError {
+    pub(crate) fn invalid_query(message: impl Into<String>) -> Self {
+        SearchError::InvalidQuery {
+            message: message.into(),
+            position: None,
+            source: None,
+        }
+    }
+
+    pub(crate) fn invalid_query_at(message: impl Into<String>, position: usize) -> Self {
+        SearchError::InvalidQuery {
+            message: message.into(),
+            position: Some(position),
+            source: None,
+        }
+    }
+
+    pub(crate) fn not_found(message: impl Into<String>) -> Self {
+        SearchError::NotFound {
+            message: message.into(),
+        }
+    }
+
+    pub(crate) fn internal(message: impl Into<String>, source: impl Into<Source>) -> Self {
+        SearchError::Internal {
+            message: message.into(),
+            source: Some(source.into()),
+        }
+    }
+
+    /// A machine readable code for the kind of error.
+    pub fn get_code(&self) -> &str {
+        match self {
+            SearchError::InvalidQuery { .. } => "invalid_query",
+            SearchError::NotFound { .. } => "not_found",
+            SearchError::IndexUnavailable { .. } => "index_unavailable",
+            SearchError::Io { .. } => "io",
+            SearchError::Internal { .. } => "internal",
+        }
+    }
+
+    pub fn position(&self) -> Option<usize> {
+        match self {
+            SearchError::InvalidQuery { position, .. } => *position,
+            _ => None,
+        }
+    }
+
+    /// Moves the position of an error found in the text of a parsed query to where that
+    /// text is in the query.
+    pub(crate) fn within_query(self, parsed_query: &ParsedQuery) -> Self {
+        match self {
+            SearchError::InvalidQuery { message, position: Some(position), source } => SearchError::InvalidQuery {
+                message,
+                position: Some(parsed_query.query_position(position)),
+                source,
+            },
+            err => err,
+        }
+    }
+
+    fn message(&self) -> &str {
+        match self {
+            SearchError::InvalidQuery { message, .. }
+            | SearchError::NotFound { message }
+            | SearchError::IndexUnavailable { message, .. }
+            | SearchError::Io { message, .. }
+            | SearchError::Internal { message, .. } => message,
+        }
+    }
 }
 
 impl fmt::Display for SearchError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "SearchError: {}", self.error)
+        match self.position() {
+            Some(position) => write!(f, "{} (at {})", self.message(), position),
+            None => write!(f, "{}", self.message()),
+        }
+    }
+}
+
+impl Error for SearchError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            SearchError::InvalidQuery { source, .. }
+            | SearchError::IndexUnavailable { source, .. }
+            | SearchError::Io { source, .. }
+            | SearchError::Internal { source, .. } => source.as_ref().map(|source| source.as_ref() as &(dyn Error + 'static)),
+            SearchError::NotFound { .. } => None,
+        }
     }
 }
 
 impl From<QueryParserError> for SearchError {
     fn from(err: QueryParserError) -> Self {
-        SearchError {
-            error: format!("{}", err),
+        SearchError::InvalidQuery {
+            message: format!("{}", err),
+            position: None,
+            source: Some(err.into()),
         }
     }
 }
 
-impl From<Error> for SearchError {
-    fn from(err: Error) -> Self {
-        SearchError {
-            error: format!("{}", err),
+/// Syntax errors in a query text carry the char offset in the text they were found at, which
+/// the strict parser does not report, so the text is parsed again leniently to find it.
+impl From<(&str, QueryParserError)> for SearchError {
+    fn from((text, err): (&str, QueryParserError)) -> Self {
+        let byte_offset = match &err {
+            QueryParserError::SyntaxError(_) => tantivy::query_grammar::parse_query_lenient(text).1.first()
+                .map(|lenient_err| lenient_err.pos),
+            _ => None,
+        };
+        SearchError::InvalidQuery {
+            message: format!("{}", err),
+            position: byte_offset.map(|byte_offset| text[..byte_offset.min(text.len())].chars().count()),
+            source: Some(err.into()),
         }
     }
 }
 
-impl From<walkdir::Error> for SearchError {
-    fn from(err: walkdir::Error) -> Self {
-        SearchError {
-            error: format!("{}", err),
-        }
+impl From<fmt::Error> for SearchError {
+    fn from(err: fmt::Error) -> Self {
+        SearchError::internal(format!("{}", err), err)
     }
 }
 
 impl From<std::io::Error> for SearchError {
     fn from(err: std::io::Error) -> Self {
-        SearchError {
-            error: format!("{}", err),
+        SearchError::Io {
+            message: format!("{}", err),
+            source: Some(err.into()),
         }
     }
 }
 
-impl From<tantivy::TantivyError> for SearchError {
-    fn from(err: tantivy::TantivyError) -> Self {
-        SearchError {
-            error: format!("{}", err),
+/// Bad arguments to index queries come from the request, like an invalid regex in a path
+/// filter. Everything about reading or writing the index makes it unavailable.
+impl From<TantivyError> for SearchError {
+    fn from(err: TantivyError) -> Self {
+        let message = format!("{}", err);
+        match err {
+            TantivyError::InvalidArgument(_) | TantivyError::FieldNotFound(_) | TantivyError::AggregationError(_) => {
+                SearchError::InvalidQuery {
+                    message,
+                    position: None,
+                    source: Some(err.into()),
+                }
+            }
+            TantivyError::OpenDirectoryError(_)
+            | TantivyError::OpenReadError(_)
+            | TantivyError::OpenWriteError(_)
+            | TantivyError::IndexAlreadyExists
+            | TantivyError::LockFailure(..)
+            | TantivyError::IoError(_)
+            | TantivyError::DataCorruption(_)
+            | TantivyError::SchemaError(_)
+            | TantivyError::IncompatibleIndex(_) => SearchError::IndexUnavailable {
+                message,
+                source: Some(err.into()),
+            },
+            _ => SearchError::internal(message, err),
         }
     }
 }
 
+impl From<git2::Error> for SearchError {
+    fn from(err: git2::Error) -> Self {
+        SearchError::Io {
+            message: format!("{}", err),
+            source: Some(err.into()),
+        }
+    }
+}
+
+impl From<globset::Error> for SearchError {
+    fn from(err: globset::Error) -> Self {
+        SearchError::internal(format!("{}", err), err)
+    }
+}
+
 impl From<task::JoinError> for SearchError {
     fn from(err: task::JoinError) -> Self {
-        SearchError {
-            error: format!("{}", err),
+        SearchError::internal(format!("{}", err), err)
+    }
+}
+
+impl From<regex::Error> for SearchError {
+    fn from(err: regex::Error) -> Self {
+        SearchError::InvalidQuery {
+            message: format!("{}", err),
+            position: None,
+            source: Some(err.into()),
+        }
+    }
+}
+
+/// Syntax errors in a pattern carry the char offset in the pattern they were found at.
+impl From<(&str, regex_syntax::Error)> for SearchError {
+    fn from((pattern, err): (&str, regex_syntax::Error)) -> Self {
+        let byte_offset = match &err {
+            regex_syntax::Error::Parse(err) => Some(err.span().start.offset),
+            regex_syntax::Error::Translate(err) => Some(err.span().start.offset),
+            _ => None,
+        };
+        SearchError::InvalidQuery {
+            message: format!("Invalid regex: {}", err.to_string().lines().last().unwrap_or_default()),
+            position: byte_offset.map(|byte_offset| pattern[..byte_offset.min(pattern.len())].chars().count()),
+            source: Some(err.into()),
         }
     }
 }
@@ -0,0 +1,147 @@
+use std::collections::HashSet;
+use std::ops::Range;
+
+use serde::{Deserialize, Serialize};
+use tantivy::query::Query;
+use tantivy::schema::Field;
+use tantivy::tokenizer::{TextAnalyzer, TokenStream};
+
+/// How many snippets are returned per result at most.
+const MAX_SNIPPETS: usize = 5;
+
+/// A run of consecutive lines around one or more matches, with 1-based line numbers.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Snippet {
+    pub start_line: usize,
+    pub end_line: usize,
+    pub lines: Vec<SnippetLine>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SnippetLine {
+    pub line_number: usize,
+    pub text: String,
+    pub highlights: Vec<Highlight>,
+}
+
+/// A matched term within a line, as byte and as char offsets into the line text.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Highlight {
+    pub byte_start: usize,
+    pub byte_end: usize,
+    pub char_start: usize,
+    pub char_end: usize,
+}
+
+/// Finds the lines of a file that contain query terms, by running them through the same
+/// tokenizer the content field is indexed with.
+pub(crate) struct SnippetGenerator {
+    terms: HashSet<String>,
+    tokenizer: TextAnalyzer,
+    context_lines: usize,
+}
+
+impl SnippetGenerator {
+    pub(crate) fn new(query: &dyn Query, fields: &[Field], tokenizer: TextAnalyzer, context_lines: usize) -> Self {
+        let mut terms = HashSet::new();
+        query.query_terms(&mut |term, _| {
+            if fields.contains(&term.field()) {
+                if let Some(text) = term.value().as_str() {
+                    terms.insert(text.to_string());
+                }
+            }
+        });
+        SnippetGenerator {
+            terms,
+            tokenizer,
+            context_lines,
+        }
+    }
+
+    pub(crate) fn generate(&mut self, content: &str) -> Vec<Snippet> {
+        if self.terms.is_empty() {
+            return vec![];
+        }
+        let context_lines = self.context_lines;
+        build_snippets(content, context_lines, |line| self.highlight_line(line))
+    }
+
+    fn highlight_line(&mut self, line: &str) -> Vec<Highlight> {
+        let mut highlights = Vec::new();
+        let mut token_stream = self.tokenizer.token_stream(line);
+        while token_stream.advance() {
+            let token = token_stream.token();
+            if self.terms.contains(&token.text) {
+                highlights.push(Highlight::new(line, token.offset_from, token.offset_to));
+            }
+        }
+        highlights
+    }
+}
+
+impl Highlight {
+    fn new(line: &str, byte_start: usize, byte_end: usize) -> Self {
+        Highlight {
+            byte_start,
+            byte_end,
+            char_start: line[..byte_start].chars().count(),
+            char_end: line[..byte_end].chars().count(),
+        }
+    }
+}
+
+/// Builds snippets around exact matches, given as sorted byte ranges into the content.
+/// Matches spanning several lines are highlighted on each of them.
+pub(crate) fn build_snippets_from_ranges(content: &str, ranges: &[Range<usize>], context_lines: usize) -> Vec<Snippet> {
+    build_snippets(content, context_lines, |line| {
+        let line_start = line.as_ptr() as usize - content.as_ptr() as usize;
+        let line_end = line_start + line.len();
+        let first_range = ranges.partition_point(|range| range.end <= line_start);
+        ranges[first_range..].iter()
+            .take_while(|range| range.start < line_end)
+            .map(|range| {
+                let start = range.start.max(line_start) - line_start;
+                let end = range.end.min(line_end) - line_start;
+                Highlight::new(line, start, end)
+            })
+            .collect()
+    })
+}
+
+/// Groups the lines that `highlight_line` finds matches in into snippets with
+/// `context_lines` lines around them, merging snippets that overlap or touch.
+fn build_snippets(content: &str, context_lines: usize, mut highlight_line: impl FnMut(&str) -> Vec<Highlight>) -> Vec<Snippet> {
+    let lines = content.lines().collect::<Vec<_>>();
+    let mut line_highlights = vec![vec![]; lines.len()];
+    let mut snippet_ranges: Vec<(usize, usize)> = Vec::new();
+    for (index, line) in lines.iter().enumerate() {
+        let highlights = highlight_line(line);
+        if highlights.is_empty() {
+            continue;
+        }
+
+        let start = index.saturating_sub(context_lines);
+        let end = (index + context_lines).min(lines.len() - 1);
+        let snippet_count = snippet_ranges.len();
+        match snippet_ranges.last_mut() {
+            Some((_, last_end)) if start <= *last_end + 1 => *last_end = end,
+            _ if snippet_count == MAX_SNIPPETS => break,
+            _ => snippet_ranges.push((start, end)),
+        }
+        line_highlights[index] = highlights;
+    }
+
+    snippet_ranges.into_iter()
+        .map(|(start, end)| Snippet {
+            start_line: start + 1,
+            end_line: end + 1,
+            lines: (start..=end)
+                .map(|index| SnippetLine {
+                    line_number: index + 1,
+                    text: lines[index].to_string(),
+                    highlights: std::mem::take(&mut line_highlights[index]),
+                })
+                .collect(),
+        })
+        .collect()
+}
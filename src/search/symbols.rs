@@ -0,0 +1,299 @@
+use std::collections::HashMap;
+use std::ops::Range;
+use std::sync::OnceLock;
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use tantivy::tokenizer::{LowerCaser, RawTokenizer, TextAnalyzer};
+
+pub(crate) const SYMBOL_TOKENIZER_NAME: &str = "symbol";
+
+/// Indexes symbol names whole and lowercased, so `sym:` matches names exactly but ignoring case.
+pub(crate) fn create_symbol_analyzer() -> TextAnalyzer {
+    TextAnalyzer::builder(RawTokenizer::default())
+        .filter(LowerCaser)
+        .build()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SymbolKind {
+    Function,
+    Method,
+    Struct,
+    Class,
+    Interface,
+    Trait,
+    Enum,
+    Type,
+    Constant,
+    Module,
+    Macro,
+}
+
+impl SymbolKind {
+    pub fn get_name(&self) -> &str {
+        match self {
+            SymbolKind::Function => "function",
+            SymbolKind::Method => "method",
+            SymbolKind::Struct => "struct",
+            SymbolKind::Class => "class",
+            SymbolKind::Interface => "interface",
+            SymbolKind::Trait => "trait",
+            SymbolKind::Enum => "enum",
+            SymbolKind::Type => "type",
+            SymbolKind::Constant => "constant",
+            SymbolKind::Module => "module",
+            SymbolKind::Macro => "macro",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "function" => Some(SymbolKind::Function),
+            "method" => Some(SymbolKind::Method),
+            "struct" => Some(SymbolKind::Struct),
+            "class" => Some(SymbolKind::Class),
+            "interface" => Some(SymbolKind::Interface),
+            "trait" => Some(SymbolKind::Trait),
+            "enum" => Some(SymbolKind::Enum),
+            "type" => Some(SymbolKind::Type),
+            "constant" => Some(SymbolKind::Constant),
+            "module" => Some(SymbolKind::Module),
+            "macro" => Some(SymbolKind::Macro),
+            _ => None,
+        }
+    }
+}
+
+/// A definition found in a file, with its 1-based line number.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Symbol {
+    pub name: String,
+    pub kind: SymbolKind,
+    pub line: usize,
+}
+
+impl Symbol {
+    /// Stores the symbol as `line:kind:name`, the name last since it is the only part that
+    /// could contain a `:`.
+    pub(crate) fn encode(&self) -> String {
+        format!("{}:{}:{}", self.line, self.kind.get_name(), self.name)
+    }
+
+    pub(crate) fn decode(value: &str) -> Option<Self> {
+        let mut parts = value.splitn(3, ':');
+        let line = parts.next()?.parse().ok()?;
+        let kind = SymbolKind::from_name(parts.next()?)?;
+        let name = parts.next()?.to_string();
+        Some(Symbol { name, kind, line })
+    }
+}
+
+/// A line pattern whose `name` group is a definition of the given kind. Functions defined on
+/// indented lines are taken for methods, as they sit in a class or impl block.
+struct SymbolRule {
+    kind: SymbolKind,
+    pattern: Regex,
+}
+
+const RUST_VISIBILITY: &str = r"(?:pub(?:\([^)]*\))?\s+)?";
+
+/// Keywords that look like a method name followed by parentheses in brace languages.
+const CONTROL_KEYWORDS: [&str; 11] = ["if", "for", "while", "switch", "catch", "return", "function", "else", "do", "try", "with"];
+
+/// The languages, by the canonical names of `languages::detect_language`, whose definition
+/// syntax the extractor knows.
+const SYMBOL_LANGUAGES: [&str; 12] = ["Rust", "Python", "JavaScript", "TypeScript", "Java", "C#", "Kotlin", "Go", "C", "C++", "Ruby", "PHP"];
+
+fn rules_for_language(language: &str) -> Vec<(SymbolKind, String)> {
+    let rules: Vec<(SymbolKind, &str)> = match language {
+        "Rust" => {
+            return vec![
+                (SymbolKind::Function, format!(r#"^\s*{}(?:(?:const|async|unsafe|extern\s+"[^"]*")\s+)*fn\s+(?P<name>\w+)"#, RUST_VISIBILITY)),
+                (SymbolKind::Struct, format!(r"^\s*{}(?:struct|union)\s+(?P<name>\w+)", RUST_VISIBILITY)),
+                (SymbolKind::Enum, format!(r"^\s*{}enum\s+(?P<name>\w+)", RUST_VISIBILITY)),
+                (SymbolKind::Trait, format!(r"^\s*{}(?:unsafe\s+)?trait\s+(?P<name>\w+)", RUST_VISIBILITY)),
+                (SymbolKind::Type, format!(r"^\s*{}type\s+(?P<name>\w+)", RUST_VISIBILITY)),
+                (SymbolKind::Constant, format!(r"^\s*{}(?:const|static)\s+(?:mut\s+)?(?P<name>\w+)\s*:", RUST_VISIBILITY)),
+                (SymbolKind::Module, format!(r"^\s*{}mod\s+(?P<name>\w+)", RUST_VISIBILITY)),
+                (SymbolKind::Macro, r"^\s*macro_rules!\s*(?P<name>\w+)".to_string()),
+            ];
+        }
+        "Python" => vec![
+            (SymbolKind::Function, r"^\s*(?:async\s+)?def\s+(?P<name>\w+)"),
+            (SymbolKind::Class, r"^\s*class\s+(?P<name>\w+)"),
+            (SymbolKind::Constant, r"^(?P<name>[A-Z][A-Z0-9_]*)\s*(?::[^=]+)?=[^=]"),
+        ],
+        "JavaScript" | "TypeScript" => vec![
+            (SymbolKind::Function, r"^\s*(?:export\s+)?(?:default\s+)?(?:async\s+)?function\s*\*?\s*(?P<name>[\w$]+)"),
+            (SymbolKind::Class, r"^\s*(?:export\s+)?(?:default\s+)?(?:abstract\s+)?class\s+(?P<name>[\w$]+)"),
+            (SymbolKind::Interface, r"^\s*(?:export\s+)?interface\s+(?P<name>[\w$]+)"),
+            (SymbolKind::Enum, r"^\s*(?:export\s+)?(?:const\s+)?enum\s+(?P<name>[\w$]+)"),
+            (SymbolKind::Type, r"^\s*(?:export\s+)?type\s+(?P<name>[\w$]+)\s*(?:<[^=]*>)?\s*="),
+            (SymbolKind::Function, r"^\s*(?:export\s+)?(?:const|let|var)\s+(?P<name>[\w$]+)\s*(?::[^=]+)?=\s*(?:async\s+)?(?:function\b|\([^)]*\)\s*(?::[^=]+)?=>|[\w$]+\s*=>)"),
+            (SymbolKind::Constant, r"^\s*(?:export\s+)?const\s+(?P<name>[A-Z][A-Z0-9_]*)\s*(?::[^=]+)?="),
+            (SymbolKind::Method, r"^\s+(?:(?:public|private|protected|static|async|readonly|abstract|override|get|set)\s+)*(?P<name>[\w$]+)\s*(?:<[^>]*>)?\s*\([^)]*\)\s*(?::\s*[^{;]+)?\{"),
+        ],
+        "Java" | "C#" | "Kotlin" => vec![
+            (SymbolKind::Class, r"^\s*(?:(?:public|private|protected|internal|static|final|abstract|sealed|partial|open|data)\s+)*(?:class|record|object)\s+(?P<name>\w+)"),
+            (SymbolKind::Interface, r"^\s*(?:(?:public|private|protected|internal|static|sealed|partial)\s+)*(?:@?interface)\s+(?P<name>\w+)"),
+            (SymbolKind::Enum, r"^\s*(?:(?:public|private|protected|internal|static)\s+)*enum\s+(?:class\s+)?(?P<name>\w+)"),
+            (SymbolKind::Struct, r"^\s*(?:(?:public|private|protected|internal|readonly|partial)\s+)*struct\s+(?P<name>\w+)"),
+            (SymbolKind::Constant, r"^\s*(?:(?:public|private|protected|internal)\s+)*(?:static\s+final|const)\s+[\w<>\[\],.?]+\s+(?P<name>\w+)\s*="),
+            (SymbolKind::Function, r"^\s*(?:(?:public|private|protected|internal|override|open|suspend|inline)\s+)*fun\s+(?:<[^>]*>\s*)?(?:[\w.]+\.)?(?P<name>\w+)"),
+            (SymbolKind::Method, r"^\s*(?:(?:public|private|protected|internal|static|final|abstract|synchronized|native|override|virtual|async|default)\s+)+(?:<[^>]*>\s+)?[\w<>\[\],.?]+(?:\s*<[^>]*>)?\s+(?P<name>\w+)\s*\("),
+        ],
+        "Go" => vec![
+            (SymbolKind::Method, r"^func\s+\([^)]*\)\s*(?P<name>\w+)"),
+            (SymbolKind::Function, r"^func\s+(?P<name>\w+)"),
+            (SymbolKind::Struct, r"^type\s+(?P<name>\w+)\s+struct\b"),
+            (SymbolKind::Interface, r"^type\s+(?P<name>\w+)\s+interface\b"),
+            (SymbolKind::Type, r"^type\s+(?P<name>\w+)"),
+            (SymbolKind::Constant, r"^const\s+(?P<name>\w+)"),
+        ],
+        "C" | "C++" => vec![
+            (SymbolKind::Macro, r"^\s*#\s*define\s+(?P<name>\w+)"),
+            (SymbolKind::Class, r"^\s*(?:template\s*<[^>]*>\s*)?class\s+(?:\w+\s+)?(?P<name>\w+)\s*(?:final\s*)?(?:[:{]|$)"),
+            (SymbolKind::Struct, r"^\s*(?:typedef\s+)?(?:struct|union)\s+(?P<name>\w+)\s*(?:[:{]|$)"),
+            (SymbolKind::Enum, r"^\s*(?:typedef\s+)?enum\s+(?:class\s+)?(?P<name>\w+)\s*(?:[:{]|$)"),
+            (SymbolKind::Module, r"^\s*namespace\s+(?P<name>\w+)"),
+            (SymbolKind::Function, r"^[A-Za-z_][\w\s\*&<>,:]*?[\s\*&](?:\w+::)*(?P<name>~?\w+)\s*\([^;]*$"),
+        ],
+        "Ruby" => vec![
+            (SymbolKind::Function, r"^\s*def\s+(?:self\.)?(?P<name>\w+[?!=]?)"),
+            (SymbolKind::Class, r"^\s*class\s+(?P<name>\w+)"),
+            (SymbolKind::Module, r"^\s*module\s+(?P<name>\w+)"),
+        ],
+        "PHP" => vec![
+            (SymbolKind::Function, r"^\s*(?:(?:public|private|protected|static|abstract|final)\s+)*function\s+(?P<name>\w+)"),
+            (SymbolKind::Class, r"^\s*(?:(?:abstract|final)\s+)?class\s+(?P<name>\w+)"),
+            (SymbolKind::Interface, r"^\s*interface\s+(?P<name>\w+)"),
+            (SymbolKind::Trait, r"^\s*trait\s+(?P<name>\w+)"),
+        ],
+        _ => vec![],
+    };
+    rules.into_iter().map(|(kind, pattern)| (kind, pattern.to_string())).collect()
+}
+
+fn compiled_rules(language: &str) -> &'static [SymbolRule] {
+    static RULES: OnceLock<HashMap<&'static str, Vec<SymbolRule>>> = OnceLock::new();
+    let rules = RULES.get_or_init(|| {
+        SYMBOL_LANGUAGES.iter()
+            .map(|language| {
+                let rules = rules_for_language(language).into_iter()
+                    .map(|(kind, pattern)| SymbolRule { kind, pattern: Regex::new(&pattern).unwrap() })
+                    .collect();
+                (*language, rules)
+            })
+            .collect()
+    });
+    rules.get(language).map_or(&[], |rules| rules.as_slice())
+}
+
+/// Finds the functions, types and constants defined in a file, line by line. This is a
+/// heuristic that knows the definition syntax of the major languages, not a parser, so
+/// unusual formatting can hide a definition. `language` is the file's detected language.
+pub(crate) fn extract_symbols(language: &str, content: &str) -> Vec<Symbol> {
+    let rules = compiled_rules(language);
+    if rules.is_empty() {
+        return vec![];
+    }
+    let mut symbols = Vec::new();
+    for (index, line) in content.lines().enumerate() {
+        let Some((kind, name)) = rules.iter().find_map(|rule| {
+            let name = rule.pattern.captures(line)?.name("name")?.as_str();
+            Some((rule.kind, name))
+        }) else {
+            continue;
+        };
+        if CONTROL_KEYWORDS.contains(&name) {
+            continue;
+        }
+        let indented = line.starts_with(char::is_whitespace);
+        let kind = match kind {
+            SymbolKind::Function if indented && language != "Go" => SymbolKind::Method,
+            kind => kind,
+        };
+        symbols.push(Symbol {
+            name: name.to_string(),
+            kind,
+            line: index + 1,
+        });
+    }
+    symbols
+}
+
+/// The byte ranges of the symbol names on their lines, sorted, for highlighting.
+pub(crate) fn name_ranges(content: &str, symbols: &[Symbol]) -> Vec<Range<usize>> {
+    let mut line_starts = vec![0];
+    line_starts.extend(content.match_indices('\n').map(|(index, _)| index + 1));
+    let mut ranges = symbols.iter()
+        .filter_map(|symbol| {
+            let line_start = *line_starts.get(symbol.line - 1)?;
+            let line_end = line_starts.get(symbol.line).map_or(content.len(), |next_start| next_start - 1);
+            let name_start = content[line_start..line_end].find(&symbol.name)?;
+            Some(line_start + name_start..line_start + name_start + symbol.name.len())
+        })
+        .collect::<Vec<_>>();
+    ranges.sort_by_key(|range| range.start);
+    ranges
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::search::languages;
+
+    fn symbol_names(language: &str, content: &str) -> Vec<(String, SymbolKind)> {
+        extract_symbols(language, content).into_iter()
+            .map(|symbol| (symbol.name, symbol.kind))
+            .collect()
+    }
+
+    #[test]
+    fn extracts_definitions_of_major_languages() {
+        let rust = "pub struct FileSearchEngine {\n}\n\nimpl FileSearchEngine {\n    pub fn new() -> Self {}\n}\nconst LIMIT: usize = 5;";
+        assert_eq!(symbol_names("Rust", rust), vec![
+            ("FileSearchEngine".to_string(), SymbolKind::Struct),
+            ("new".to_string(), SymbolKind::Method),
+            ("LIMIT".to_string(), SymbolKind::Constant),
+        ]);
+        let typescript = "export interface Props {}\nexport const useSearch = () => {\n  if (ready) {\n  }\n};\nclass Store {\n  async load(id: string): Promise<void> {\n  }\n}";
+        assert_eq!(symbol_names("TypeScript", typescript), vec![
+            ("Props".to_string(), SymbolKind::Interface),
+            ("useSearch".to_string(), SymbolKind::Function),
+            ("Store".to_string(), SymbolKind::Class),
+            ("load".to_string(), SymbolKind::Method),
+        ]);
+        let go = "func (s *Server) Start() error {\n}\nfunc main() {\n}\ntype Config struct {\n}";
+        assert_eq!(symbol_names("Go", go), vec![
+            ("Start".to_string(), SymbolKind::Method),
+            ("main".to_string(), SymbolKind::Function),
+            ("Config".to_string(), SymbolKind::Struct),
+        ]);
+        let java = "public class Parser {\n    private static final int MAX = 3;\n    public List<String> parse(String input) {\n        return call(input);\n    }\n}";
+        assert_eq!(symbol_names("Java", java), vec![
+            ("Parser".to_string(), SymbolKind::Class),
+            ("MAX".to_string(), SymbolKind::Constant),
+            ("parse".to_string(), SymbolKind::Method),
+        ]);
+    }
+
+    #[test]
+    fn extracts_definitions_by_detected_language() {
+        let script = "#!/usr/bin/env python3
+def main():
+    pass
+";
+        let language = languages::detect_language("bin/deploy", script);
+        assert_eq!(symbol_names(language, script), vec![("main".to_string(), SymbolKind::Function)]);
+        let header = "#include <vector>
+class Index {
+};
+";
+        let language = languages::detect_language("src/index.h", header);
+        assert_eq!(symbol_names(language, header), vec![("Index".to_string(), SymbolKind::Class)]);
+        assert!(symbol_names("Markdown", "# def main():").is_empty());
+    }
+}
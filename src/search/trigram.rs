@@ -0,0 +1,62 @@
+use std::collections::BTreeSet;
+
+use tantivy::tokenizer::{LowerCaser, NgramTokenizer, TextAnalyzer, TokenStream};
+
+pub(crate) const TRIGRAM_TOKENIZER_NAME: &str = "trigram";
+
+/// Splits text into lowercased overlapping three character grams, so any substring of at
+/// least three characters can be narrowed down to the files containing all its trigrams.
+pub(crate) fn create_trigram_analyzer() -> TextAnalyzer {
+    TextAnalyzer::builder(NgramTokenizer::new(3, 3, false).unwrap())
+        .filter(LowerCaser)
+        .build()
+}
+
+/// The distinct trigrams of a literal, empty if it is too short to have any.
+pub(crate) fn trigrams(literal: &str) -> BTreeSet<String> {
+    let mut analyzer = create_trigram_analyzer();
+    let mut token_stream = analyzer.token_stream(literal);
+    let mut trigrams = BTreeSet::new();
+    while token_stream.advance() {
+        trigrams.insert(token_stream.token().text.clone());
+    }
+    trigrams
+}
+
+/// A boolean combination of trigrams that every matching file has to contain.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum TrigramQuery {
+    /// No restriction, every file is a candidate.
+    All,
+    Trigram(String),
+    And(Vec<TrigramQuery>),
+    Or(Vec<TrigramQuery>),
+}
+
+impl TrigramQuery {
+    /// Files containing the literal contain all its trigrams.
+    pub(crate) fn literal(literal: &str) -> Self {
+        Self::and(trigrams(literal).into_iter().map(TrigramQuery::Trigram).collect())
+    }
+
+    pub(crate) fn and(queries: Vec<TrigramQuery>) -> Self {
+        let mut queries = queries.into_iter()
+            .filter(|query| *query != TrigramQuery::All)
+            .collect::<Vec<_>>();
+        match queries.len() {
+            0 => TrigramQuery::All,
+            1 => queries.remove(0),
+            _ => TrigramQuery::And(queries),
+        }
+    }
+
+    pub(crate) fn or(mut queries: Vec<TrigramQuery>) -> Self {
+        if queries.is_empty() || queries.contains(&TrigramQuery::All) {
+            return TrigramQuery::All;
+        }
+        match queries.len() {
+            1 => queries.remove(0),
+            _ => TrigramQuery::Or(queries),
+        }
+    }
+}
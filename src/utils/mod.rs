@@ -1,3 +1,45 @@
 pub fn convert_datetime_chrono_to_tantivy(dt: &chrono::DateTime<chrono::Utc>) -> tantivy::DateTime {
     tantivy::DateTime::from_timestamp_millis(dt.timestamp_millis())
-}
\ No newline at end of file
+}
+
+/// Lowercase hex of the bytes, as used for hashes and opaque tokens.
+pub fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+pub fn decode_hex(value: &str) -> Option<Vec<u8>> {
+    if !value.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..value.len())
+        .step_by(2)
+        .map(|index| value.get(index..index + 2).and_then(|byte| u8::from_str_radix(byte, 16).ok()))
+        .collect()
+}
+
+/// Parses a date like `2026-01-01` into the span of that day in UTC, or an RFC 3339 time
+/// like `2026-01-01T12:00:00Z` into the millisecond it names. The end is exclusive.
+pub fn parse_date_span(value: &str) -> Option<(chrono::DateTime<chrono::Utc>, chrono::DateTime<chrono::Utc>)> {
+    if let Ok(date) = chrono::NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+        let start = date.and_hms_opt(0, 0, 0)?.and_utc();
+        return Some((start, start + chrono::Duration::days(1)));
+    }
+    let time = chrono::DateTime::parse_from_rfc3339(value).ok()?.with_timezone(&chrono::Utc);
+    Some((time, time + chrono::Duration::milliseconds(1)))
+}
+
+/// Parses sizes like `1048576`, `512kb`, `10MB` or `1.5 GiB` into bytes, using 1024 based units.
+pub fn parse_byte_size(value: &str) -> Option<u64> {
+    let value = value.trim().to_lowercase();
+    let unit_start = value.find(|c: char| !c.is_ascii_digit() && c != '.').unwrap_or(value.len());
+    let (number, unit) = value.split_at(unit_start);
+    let number: f64 = number.parse().ok()?;
+    let multiplier: u64 = match unit.trim() {
+        "" | "b" => 1,
+        "k" | "kb" | "kib" => 1 << 10,
+        "m" | "mb" | "mib" => 1 << 20,
+        "g" | "gb" | "gib" => 1 << 30,
+        _ => return None,
+    };
+    Some((number * multiplier as f64) as u64)
+}
@@ -0,0 +1,175 @@
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+
+use crate::config;
+use crate::search::FileSearchEngine;
+
+/// Keeps the filesystem subscriptions alive, dropping it stops watching.
+pub struct RepoWatcher {
+    _watcher: RecommendedWatcher,
+}
+
+pub fn start_watcher(engine: Arc<FileSearchEngine>) -> notify::Result<RepoWatcher> {
+    let config = engine.get_config().clone();
+    log::info!("Starting filesystem watcher for {} repos", config.repos.len());
+
+    let (tx, rx) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(tx)?;
+    let repo_roots = config.repos.iter().map(repo_root).collect::<Vec<_>>();
+    for (repo, root) in config.repos.iter().zip(&repo_roots) {
+        let watch_path = watch_path(repo, root);
+        watcher.watch(&watch_path, RecursiveMode::Recursive)?;
+        log::info!("Watching repo: {} ({:?})", repo.name, watch_path);
+    }
+
+    thread::Builder::new()
+        .name("repo-watcher".to_string())
+        .spawn(move || watch_loop(engine, config, repo_roots, rx))?;
+
+    Ok(RepoWatcher { _watcher: watcher })
+}
+
+/// The absolute path of a repo, as the paths of filesystem events are, even when the repo
+/// path is configured relative to the working directory.
+fn repo_root(repo: &config::Repo) -> PathBuf {
+    Path::new(&repo.path).canonicalize().unwrap_or_else(|err| {
+        log::warn!("Failed to resolve path of repo {}: {}", repo.name, err);
+        PathBuf::from(&repo.path)
+    })
+}
+
+/// Git repos are indexed from their committed tree, so only ref and object changes in the
+/// git directory matter for them, not edits in the working copy.
+fn watch_path(repo: &config::Repo, root: &Path) -> PathBuf {
+    if repo.type_ == "git" && root.join(".git").is_dir() {
+        root.join(".git")
+    } else {
+        root.to_path_buf()
+    }
+}
+
+fn watch_loop(engine: Arc<FileSearchEngine>, config: config::Config, repo_roots: Vec<PathBuf>, rx: Receiver<notify::Result<Event>>) {
+    let debounce = Duration::from_millis(config.watcher.debounce_ms);
+    let max_batch_delay = Duration::from_millis(config.watcher.max_batch_delay_ms);
+
+    while let Ok(event) = rx.recv() {
+        let mut changed_paths = HashSet::new();
+        collect_event_paths(event, &mut changed_paths);
+
+        // Keep collecting until the filesystem has been quiet for `debounce`, but never hold
+        // a batch back for longer than `max_batch_delay` while events keep streaming in.
+        let batch_deadline = Instant::now() + max_batch_delay;
+        let mut disconnected = false;
+        while Instant::now() < batch_deadline {
+            match rx.recv_timeout(debounce) {
+                Ok(event) => collect_event_paths(event, &mut changed_paths),
+                Err(RecvTimeoutError::Timeout) => break,
+                Err(RecvTimeoutError::Disconnected) => {
+                    disconnected = true;
+                    break;
+                }
+            }
+        }
+
+        apply_changes(&engine, &config, &repo_roots, changed_paths);
+        if disconnected {
+            break;
+        }
+    }
+    log::info!("Filesystem watcher stopped");
+}
+
+fn collect_event_paths(event: notify::Result<Event>, changed_paths: &mut HashSet<PathBuf>) {
+    match event {
+        Ok(event) => match event.kind {
+            EventKind::Create(_) | EventKind::Modify(_) | EventKind::Remove(_) => {
+                changed_paths.extend(event.paths);
+            }
+            _ => {}
+        },
+        Err(err) => log::error!("Filesystem watcher error: {:?}", err),
+    }
+}
+
+/// Groups the changed paths by repo and applies them. The paths are moved below the repo
+/// path as configured, which is what the index is keyed on.
+fn apply_changes(engine: &FileSearchEngine, config: &config::Config, repo_roots: &[PathBuf], changed_paths: HashSet<PathBuf>) {
+    let mut repo_changes: HashMap<&str, Vec<PathBuf>> = HashMap::new();
+    for path in changed_paths {
+        let repo_path = config.repos.iter().zip(repo_roots)
+            .find_map(|(repo, root)| path.strip_prefix(root).ok().map(|relative_path| (repo, relative_path)));
+        if let Some((repo, relative_path)) = repo_path {
+            repo_changes.entry(&repo.name).or_default().push(Path::new(&repo.path).join(relative_path));
+        }
+    }
+
+    for repo in &config.repos {
+        let Some(paths) = repo_changes.get(repo.name.as_str()) else {
+            continue;
+        };
+        log::info!("Applying {} filesystem changes to repo: {}", paths.len(), repo.name);
+        let result = match repo.type_.as_str() {
+            "git" => engine.sync_repo(repo),
+            _ => engine.apply_file_changes(repo, paths),
+        };
+        if let Err(err) = result {
+            log::error!("Failed to apply filesystem changes to repo {}: {}", repo.name, err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use notify::event::{CreateKind, ModifyKind, RemoveKind};
+    use crate::search::{SearchEngine, SearchOptions};
+
+    async fn search_file_paths(engine: &FileSearchEngine, query: &str) -> Vec<String> {
+        let results = engine.search(SearchOptions::for_query(query)).await.unwrap().results;
+        let mut file_paths = results.into_iter().map(|result| result.data.file_path).collect::<Vec<_>>();
+        file_paths.sort();
+        file_paths
+    }
+
+    #[tokio::test]
+    async fn batched_events_of_repo_with_non_canonical_path_update_the_index() {
+        // Events carry canonical paths, which the configured path has to be resolved to
+        let temp_dir = tempfile::tempdir().unwrap();
+        let repo_path = temp_dir.path().join("repo");
+        fs::create_dir(&repo_path).unwrap();
+        fs::write(repo_path.join("changed.rs"), "fn old() {}").unwrap();
+        fs::write(repo_path.join("deleted.rs"), "fn dingo() {}").unwrap();
+
+        let config = config::test_config(&repo_path.join("..").join("repo"), "watcher: {debounce_ms: 10}");
+        let engine = Arc::new(FileSearchEngine::new(&config).unwrap());
+        engine.initialize().await.unwrap();
+        assert_eq!(search_file_paths(&engine, "dingo").await, vec!["deleted.rs"]);
+
+        let root = repo_path.canonicalize().unwrap();
+        fs::write(root.join("changed.rs"), "fn dingo() {}").unwrap();
+        fs::write(root.join("added.rs"), "fn dingo_added() { dingo(); }").unwrap();
+        fs::remove_file(root.join("deleted.rs")).unwrap();
+        let (tx, rx) = mpsc::channel();
+        let events = [
+            Event::new(EventKind::Modify(ModifyKind::Any)).add_path(root.join("changed.rs")),
+            Event::new(EventKind::Create(CreateKind::File)).add_path(root.join("added.rs")),
+            Event::new(EventKind::Modify(ModifyKind::Any)).add_path(root.join("added.rs")),
+            Event::new(EventKind::Remove(RemoveKind::File)).add_path(root.join("deleted.rs")),
+        ];
+        for event in events {
+            tx.send(Ok(event)).unwrap();
+        }
+        drop(tx);
+
+        let repo_roots = config.repos.iter().map(repo_root).collect();
+        watch_loop(engine.clone(), config, repo_roots, rx);
+        assert_eq!(search_file_paths(&engine, "dingo").await, vec!["added.rs", "changed.rs"]);
+    }
+}
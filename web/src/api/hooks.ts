@@ -4,17 +4,19 @@ import api from "@/api/index.ts";
 
 export function useSearchResults(query: string) {
   const [results, setResults] = useState<ResultItem[]>([]);
+  const [total, setTotal] = useState(0);
   const [loading, setLoading] = useState(false);
 
   useEffect(() => {
     if (query) {
       setLoading(true);
-      api.search({query, limit: 20}).then((res) => {
-        setResults(res.data);
+      api.search({query, limit: 20, include_content: true}).then((res) => {
+        setResults(res.data.results);
+        setTotal(res.data.total);
         setLoading(false);
       });
     }
   }, [query]);
 
-  return {results, loading};
+  return {results, total, loading};
 }
\ No newline at end of file
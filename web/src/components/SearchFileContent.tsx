@@ -55,7 +55,7 @@ export const SearchFileContent: React.FC<FileContentProps> = ({item}) => {
             }
         }}
         >
-          {item.file_content}
+          {item.file_content ?? ''}
         </SyntaxHighlighter>
       </pre>
     </div>
@@ -2,11 +2,16 @@
 export type StandardResponse<T> = {
   data: T;
   error: string | null;
+  error_code?: ErrorCode;
+  error_position?: number;
   time_taken: number;
 }
 
+export type ErrorCode = "invalid_query" | "not_found" | "index_unavailable" | "io" | "internal";
+
 export interface ResultItem {
   _score: number;
+  file_id: string;
   repo_name: string;
   repo_path: string;
   repo_type: string;
@@ -16,14 +21,171 @@ export interface ResultItem {
   file_size: number;
   file_last_updated: Date | string;
   file_language: string;
-  file_content: string;
+  file_content?: string;
+  file_hash: string;
+  commit_sha: string;
+  branch: string[];
+  file_content_policy: 'full' | 'truncated' | 'metadata_only' | 'name_only';
+  chunks: MatchedChunk[];
+  snippets: Snippet[];
+  symbols?: Symbol[];
+  explanation?: ScoreExplanation;
+}
+
+export type SymbolKind = 'function' | 'method' | 'struct' | 'class' | 'interface' | 'trait' | 'enum' | 'type' | 'constant' | 'module' | 'macro';
+
+export interface Symbol {
+  name: string;
+  kind: SymbolKind;
+  line: number;
+}
+
+export interface ScoreExplanation {
+  base_score: number;
+  demotion: number;
+  recency: number;
+  score: number;
+  details?: unknown;
+}
+
+export interface Snippet {
+  start_line: number;
+  end_line: number;
+  lines: SnippetLine[];
+}
+
+export interface SnippetLine {
+  line_number: number;
+  text: string;
+  highlights: Highlight[];
+}
+
+export interface Highlight {
+  byte_start: number;
+  byte_end: number;
+  char_start: number;
+  char_end: number;
+}
+
+export interface MatchedChunk {
+  _score: number;
+  start_line: number;
+  end_line: number;
 }
 
 export interface HealthResponse {
   status: string;
 }
 
+export interface IndexStatusResponse {
+  repos: RepoIndexStatus[];
+}
+
+export interface RepoIndexStatus {
+  name: string;
+  skipped_files: SkippedFile[];
+}
+
+export interface SkippedFile {
+  file_id: string;
+  reason: string;
+}
+
+export type SearchMode = 'text' | 'literal' | 'regex';
+
+export type SortBy = 'score' | 'modified' | 'size' | 'path';
+
 export interface SearchRequest {
   query: string;
+  mode?: SearchMode;
+  sort_by?: SortBy;
+  limit?: number;
+  offset?: number;
+  cursor?: string;
+  include_content?: boolean;
+  context_lines?: number;
+  explain?: boolean;
+}
+
+export interface SearchResponse {
+  results: ResultItem[];
+  total: number;
+  offset: number;
+  next_cursor: string | null;
+  facets: Facets;
+  time_taken: number;
+}
+
+export interface FacetCount {
+  value: string;
+  count: number;
+}
+
+export interface Facets {
+  repo_name: FacetCount[];
+  file_language: FacetCount[];
+  file_ext: FacetCount[];
+  top_level_dir: FacetCount[];
+}
+
+export interface ReferencesRequest {
+  name?: string;
+  repo?: string;
+  path?: string;
+  line?: number;
+  column?: number;
   limit?: number;
 }
+
+export interface ReferencesResponse {
+  name: string;
+  files: FileReferences[];
+  total_definitions: number;
+  total_references: number;
+  time_taken: number;
+}
+
+export interface FileReferences {
+  file_id: string;
+  repo_name: string;
+  file_path: string;
+  definitions: Symbol[];
+  references: Reference[];
+}
+
+export interface Reference {
+  line: number;
+  column: number;
+  text: string;
+}
+
+export interface CodeIntelRequest {
+  repo: string;
+  path: string;
+  line: number;
+  column: number;
+  limit?: number;
+}
+
+export interface CodeIntelResponse {
+  symbol: string;
+  hover: string | null;
+  definitions: Location[];
+  references: Location[];
+  total_references: number;
+  time_taken: number;
+}
+
+export interface Location {
+  repo_name: string;
+  file_path: string;
+  range: SourceRange;
+  is_definition: boolean;
+}
+
+export interface SourceRange {
+  start_line: number;
+  start_column: number;
+  end_line: number;
+  end_column: number;
+}